@@ -114,6 +114,38 @@ fn scoped_yield_bench(b: &mut Bencher) {
     g.raw_send(None);
 }
 
+// compare with `scoped_yield_bench` for the cost of the hooks,
+// the switch without them must not be slowed down by their checks
+#[bench]
+fn hooked_yield_bench(b: &mut Bencher) {
+    struct Nop;
+    impl GeneratorObserver for Nop {}
+
+    let mut g = Gn::new_scoped(|mut s| {
+        let mut i = 0;
+        while let Some(x) = s.yield_(i) {
+            i += 1;
+            assert_eq!(x, i);
+        }
+        20usize
+    });
+    g.set_observer(Some(std::sync::Arc::new(Nop)));
+    g.enable_stats();
+
+    // start g
+    g.raw_send(None);
+
+    let mut i: usize = 1;
+    b.iter(|| {
+        let data: usize = g.send(i);
+        assert_eq!(data, i);
+        i += 1;
+    });
+
+    // quit g
+    g.raw_send(None);
+}
+
 #[bench]
 fn create_gen(b: &mut Bencher) {
     b.iter(|| {
@@ -163,6 +195,82 @@ fn init_gen(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn done_panic_bench(b: &mut Bencher) {
+    b.iter(|| {
+        let g = Gn::<()>::new_scoped(|mut s| {
+            s.yield_(1);
+            s.yield_(2);
+            done!();
+        });
+        for v in g {
+            test::black_box(v);
+        }
+    });
+}
+
+#[bench]
+fn done_return_bench(b: &mut Bencher) {
+    b.iter(|| {
+        let g = Gn::<()>::new_scoped_done(|mut s| {
+            s.yield_(1);
+            s.yield_(2);
+            None
+        });
+        for v in g {
+            test::black_box(v);
+        }
+    });
+}
+
+#[bench]
+fn yield_from_tree_bench(b: &mut Bencher) {
+    // walk a small complete binary tree by recursive delegation,
+    // the leaves yield most of the values so the creation is not measured
+    fn walk(depth: usize) -> Generator<'static, (), usize> {
+        Gn::new_scoped(move |mut s| {
+            if depth == 0 {
+                for i in 0..1024 {
+                    s.yield_(i);
+                }
+            } else {
+                s.yield_from(walk(depth - 1));
+                s.yield_from(walk(depth - 1));
+            }
+            depth
+        })
+    }
+
+    b.iter(|| {
+        let n = walk(3).count();
+        // 8 leaves and the return of the 15 nodes
+        assert_eq!(n, 8 * 1024 + 15);
+    });
+}
+
+#[bench]
+fn yield_from_deep_bench(b: &mut Bencher) {
+    // resume through a chain of delegating generators
+    fn chain(depth: usize) -> Generator<'static, (), usize> {
+        Gn::new_scoped(move |mut s| {
+            if depth == 0 {
+                for i in 0.. {
+                    s.yield_(i);
+                }
+            }
+            s.yield_from(chain(depth - 1));
+            depth
+        })
+    }
+
+    let mut g = chain(64);
+    let mut i = 0;
+    b.iter(|| {
+        assert_eq!(g.resume(), Some(i));
+        i += 1;
+    });
+}
+
 #[bench]
 fn fnbox_bench(b: &mut Bencher) {
     b.iter(|| {
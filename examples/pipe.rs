@@ -26,4 +26,19 @@ fn main() {
     for (i, sum) in sum(square(0..20)).enumerate() {
         println!("square_sum_{i:<2} = {sum:^4}");
     }
+
+    // the same pipeline with combinators, the sum receives the input by send
+    let sum = Gn::<u32>::new_scoped_done(|mut s| {
+        let mut acc = 0;
+        let mut v = s.get_yield();
+        while let Some(i) = v {
+            acc += i;
+            v = s.yield_(acc);
+        }
+        None
+    });
+
+    for (i, sum) in square(0..20).then(sum).enumerate() {
+        println!("square_sum_{i:<2} = {sum:^4}");
+    }
 }
@@ -0,0 +1,281 @@
+//! # generator combinators
+//!
+//! adapters that keep the send/yield protocol of the generators
+//!
+//! each combinator creates a new generator with the default stack size
+//! that drives the original ones, the para passed to the new generator
+//! is forwarded to the original one just like `yield_from`
+//!
+
+use crate::gen_impl::{GeneratorObj, DEFAULT_STACK_SIZE};
+use crate::scope::Scope;
+
+macro_rules! impl_combinators {
+    ($local:literal, $($send:tt)*) => {
+        impl<'a, A: 'a $($send)*, T: 'a $($send)*> GeneratorObj<'a, A, T, $local> {
+            /// convert the yielded values by `f`
+            pub fn map_yield<U, F>(self, mut f: F) -> GeneratorObj<'a, A, U, $local>
+            where
+                U: 'a $($send)*,
+                F: FnMut(T) -> U + 'a $($send)*,
+            {
+                let mut g = self;
+                GeneratorObj::new_scoped_done_unchecked(
+                    DEFAULT_STACK_SIZE,
+                    move |mut s: Scope<'_, 'a, A, U>| {
+                        let mut p = s.get_yield();
+                        loop {
+                            let v = f(g.raw_send(p)?);
+                            if g.is_done() {
+                                return Some(v);
+                            }
+                            p = unsafe { s.yield_unsafe(v) };
+                        }
+                    },
+                )
+            }
+
+            /// convert the send paras by `f` before passing them in
+            pub fn map_send<B, F>(self, mut f: F) -> GeneratorObj<'a, B, T, $local>
+            where
+                B: 'a $($send)*,
+                F: FnMut(B) -> A + 'a $($send)*,
+            {
+                let mut g = self;
+                GeneratorObj::new_scoped_done_unchecked(
+                    DEFAULT_STACK_SIZE,
+                    move |mut s: Scope<'_, 'a, B, T>| {
+                        let mut p = s.get_yield();
+                        loop {
+                            let v = g.raw_send(p.map(&mut f))?;
+                            if g.is_done() {
+                                return Some(v);
+                            }
+                            p = unsafe { s.yield_unsafe(v) };
+                        }
+                    },
+                )
+            }
+
+            /// only yield the values that match the predicate
+            ///
+            /// the skipped values are answered with a `None` para
+            pub fn filter<P>(self, mut pred: P) -> GeneratorObj<'a, A, T, $local>
+            where
+                P: FnMut(&T) -> bool + 'a $($send)*,
+            {
+                let mut g = self;
+                GeneratorObj::new_scoped_done_unchecked(
+                    DEFAULT_STACK_SIZE,
+                    move |mut s: Scope<'_, 'a, A, T>| {
+                        let mut p = s.get_yield();
+                        loop {
+                            let v = g.raw_send(p)?;
+                            let done = g.is_done();
+                            if !pred(&v) {
+                                if done {
+                                    return None;
+                                }
+                                p = None;
+                                continue;
+                            }
+                            if done {
+                                return Some(v);
+                            }
+                            p = unsafe { s.yield_unsafe(v) };
+                        }
+                    },
+                )
+            }
+
+            /// run the generator and then the `next` one
+            ///
+            /// the para passed after the last value of the first generator
+            /// goes to the first resume of the `next` one
+            pub fn chain(self, next: Self) -> GeneratorObj<'a, A, T, $local> {
+                let mut g = self;
+                let mut next = next;
+                GeneratorObj::new_scoped_done_unchecked(
+                    DEFAULT_STACK_SIZE,
+                    move |mut s: Scope<'_, 'a, A, T>| {
+                        let mut p = s.get_yield();
+                        while !g.is_done() {
+                            match g.raw_send(p.take()) {
+                                Some(v) => p = unsafe { s.yield_unsafe(v) },
+                                None => break,
+                            }
+                        }
+                        drop(g);
+                        loop {
+                            let v = next.raw_send(p)?;
+                            if next.is_done() {
+                                return Some(v);
+                            }
+                            p = unsafe { s.yield_unsafe(v) };
+                        }
+                    },
+                )
+            }
+
+            /// feed the yielded values into the `next` generator as its paras
+            /// and yield out what it yields back
+            ///
+            /// it's finished when either of them is finished
+            pub fn then<U>(
+                self,
+                next: GeneratorObj<'a, T, U, $local>,
+            ) -> GeneratorObj<'a, A, U, $local>
+            where
+                U: 'a $($send)*,
+            {
+                let mut g = self;
+                let mut next = next;
+                GeneratorObj::new_scoped_done_unchecked(
+                    DEFAULT_STACK_SIZE,
+                    move |mut s: Scope<'_, 'a, A, U>| {
+                        let mut p = s.get_yield();
+                        loop {
+                            let v = g.raw_send(p)?;
+                            let done = g.is_done();
+                            let u = next.raw_send(Some(v))?;
+                            if done || next.is_done() {
+                                return Some(u);
+                            }
+                            p = unsafe { s.yield_unsafe(u) };
+                        }
+                    },
+                )
+            }
+
+            /// drive the generator by sending the items of the iterator
+            ///
+            /// it's finished when the iterator is exhausted
+            pub fn zip_feed<I>(self, iter: I) -> GeneratorObj<'a, (), T, $local>
+            where
+                I: IntoIterator<Item = A>,
+                I::IntoIter: 'a $($send)*,
+            {
+                let mut g = self;
+                let mut iter = iter.into_iter();
+                GeneratorObj::new_scoped_done_unchecked(
+                    DEFAULT_STACK_SIZE,
+                    move |mut s: Scope<'_, 'a, (), T>| loop {
+                        let v = g.raw_send(Some(iter.next()?))?;
+                        if g.is_done() {
+                            return Some(v);
+                        }
+                        s.yield_with(v);
+                    },
+                )
+            }
+
+            /// yield the values until the predicate matches
+            ///
+            /// the matched value is the last one, then the generator is cancelled
+            pub fn take_until<P>(self, mut pred: P) -> GeneratorObj<'a, A, T, $local>
+            where
+                P: FnMut(&T) -> bool + 'a $($send)*,
+            {
+                let mut g = self;
+                GeneratorObj::new_scoped_done_unchecked(
+                    DEFAULT_STACK_SIZE,
+                    move |mut s: Scope<'_, 'a, A, T>| {
+                        let mut p = s.get_yield();
+                        loop {
+                            let v = g.raw_send(p)?;
+                            if g.is_done() || pred(&v) {
+                                return Some(v);
+                            }
+                            p = unsafe { s.yield_unsafe(v) };
+                        }
+                    },
+                )
+            }
+        }
+    };
+}
+
+impl_combinators!(false, + Send);
+impl_combinators!(true,);
+
+impl<'a, A, T, const LOCAL: bool> GeneratorObj<'a, A, T, LOCAL> {
+    /// create a generator wrapper that can peek the next value
+    pub fn peekable(self) -> Peekable<'a, A, T, LOCAL> {
+        Peekable {
+            gen: self,
+            peeked: None,
+        }
+    }
+}
+
+/// a generator wrapper with the `peek` method, created by `peekable`
+///
+/// peeking resumes the generator with the para set by `set_para`, when
+/// there is a peeked value the para of the next send is kept and passed
+/// in the next resume of the generator
+pub struct Peekable<'a, A, T, const LOCAL: bool> {
+    gen: GeneratorObj<'a, A, T, LOCAL>,
+    peeked: Option<Option<T>>,
+}
+
+impl<'a, A, T, const LOCAL: bool> Peekable<'a, A, T, LOCAL> {
+    /// get the next value without consuming it
+    pub fn peek(&mut self) -> Option<&T> {
+        let gen = &mut self.gen;
+        self.peeked.get_or_insert_with(|| gen.resume()).as_ref()
+    }
+
+    /// prepare the para that passed into generator before send
+    #[inline]
+    pub fn set_para(&mut self, para: A) {
+        self.gen.set_para(para);
+    }
+
+    /// resume the generator without touch the para
+    /// you should call `set_para` before this method
+    pub fn resume(&mut self) -> Option<T> {
+        match self.peeked.take() {
+            Some(v) => v,
+            None => self.gen.resume(),
+        }
+    }
+
+    /// `raw_send`
+    pub fn raw_send(&mut self, para: Option<A>) -> Option<T> {
+        match self.peeked.take() {
+            Some(v) => {
+                if let Some(para) = para {
+                    self.gen.set_para(para);
+                }
+                v
+            }
+            None => self.gen.raw_send(para),
+        }
+    }
+
+    /// send interface
+    pub fn send(&mut self, para: A) -> T {
+        let ret = self.raw_send(Some(para));
+        ret.expect("send got None return")
+    }
+
+    /// is finished, a peeked value is not consumed yet
+    pub fn is_done(&self) -> bool {
+        match self.peeked {
+            Some(ref v) => v.is_none(),
+            None => self.gen.is_done(),
+        }
+    }
+
+    /// get the inner generator, the peeked value is discarded
+    pub fn into_inner(self) -> GeneratorObj<'a, A, T, LOCAL> {
+        self.gen
+    }
+}
+
+impl<T, const LOCAL: bool> Iterator for Peekable<'_, (), T, LOCAL> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.resume()
+    }
+}
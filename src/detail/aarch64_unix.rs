@@ -52,6 +52,12 @@ impl Registers {
             prefetch(ptr.add(8)); // RSP + 8
         }
     }
+
+    /// the saved stack pointer
+    #[inline]
+    pub fn sp(&self) -> usize {
+        self.gpr[12]
+    }
 }
 
 pub fn initialize_call_frame(
@@ -78,12 +84,53 @@ pub fn initialize_call_frame(
     regs.gpr[X20] = arg2 as usize;
     regs.gpr[X21] = fptr as usize;
 
-    // Aarch64 current stack frame pointer
-    regs.gpr[FP] = sp as usize;
+    // the last frame pointer is the frame record at the stack bottom
+    regs.gpr[FP] = sp.wrapping_sub(2) as usize;
 
     regs.gpr[LR] = bootstrap_green_task as *const () as usize;
 
     // setup the init stack
     // this is prepared for the swap context
-    regs.gpr[SP] = sp as usize;
+    // the slot at the init sp holds the resumer registers, see `set_resumer`
+    regs.gpr[SP] = sp.wrapping_sub(4) as usize;
+    unsafe {
+        *sp.sub(4) = 0;
+        *sp.sub(3) = 0;
+        // the frame record linked to the resumer
+        *sp.sub(2) = 0;
+        *sp.sub(1) = stitch_ret();
+    }
+}
+
+/// record the registers of the resumer at the stack bottom
+///
+/// the unwinder continues from the generator init frame into the resumer,
+/// the frame pointer walkers go on by the saved fp and lr which are
+/// a frame record
+#[cfg(not(target_vendor = "apple"))]
+#[inline]
+pub fn set_resumer(stack_end: usize, regs: &Registers) {
+    const FP: usize = 29 - 19;
+
+    let sp = align_down(stack_end as *mut usize);
+    unsafe {
+        *sp.sub(4) = regs as *const _ as usize;
+        *sp.sub(2) = &regs.gpr[FP] as *const _ as usize;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_vendor = "apple")] {
+        // no unwind info to stitch the frames
+        fn stitch_ret() -> usize {
+            0
+        }
+    } else {
+        fn stitch_ret() -> usize {
+            extern "C" {
+                fn generator_stitch_ret();
+            }
+            generator_stitch_ret as *const () as usize
+        }
+    }
 }
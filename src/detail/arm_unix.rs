@@ -39,6 +39,12 @@ impl Registers {
             prefetch(ptr.add(1)); // SP + 4
         }
     }
+
+    /// the saved stack pointer
+    #[inline]
+    pub fn sp(&self) -> usize {
+        self.gpr[8 /* SP */]
+    }
 }
 
 pub fn initialize_call_frame(
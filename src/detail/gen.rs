@@ -1,3 +1,4 @@
+use crate::gen_panic::{GeneratorPanic, PanicReport};
 use crate::rt::ContextStack;
 use crate::stack::{overflow, Func};
 use crate::yield_::yield_now;
@@ -5,29 +6,38 @@ use crate::Error;
 use std::any::Any;
 use std::panic;
 
-/// don't print panic info for Done/Cancel
-fn catch_unwind_filter<F: FnOnce() -> R + panic::UnwindSafe, R>(f: F) -> std::thread::Result<R> {
-    use std::sync::Once;
-    static INIT: Once = Once::new();
-    INIT.call_once(|| {
-        let prev_hook = panic::take_hook();
-        panic::set_hook(Box::new(move |info| {
-            // this is not an error at all, ignore it
-            if let Some(Error::Cancel | Error::Done) = info.payload().downcast_ref::<Error>() {
-                return;
-            }
-            prev_hook(info);
-        }));
-    });
-
-    panic::catch_unwind(f)
+fn check_err(cause: Box<dyn Any + Send + 'static>) {
+    // this is not an error at all, ignore it
+    if let Some(Error::Cancel | Error::Done) = cause.downcast_ref::<Error>() {
+        return;
+    }
+
+    let ctx = ContextStack::current().top();
+    if let PanicReport::Site = ctx.panic_report {
+        error!("set panic inside generator '{}'", ctx.display_name());
+    }
+    // mark it as panicked, the err may be taken by the parent
+    ctx._ref = 3;
+    // keep the first panic if the cleanup panics again
+    if ctx.err.is_none() {
+        ctx.err = Some(GeneratorPanic::wrap(ctx, cause));
+    }
 }
 
+/// the function run on the stack of a suspended generator instead of its own
+/// function, it's passed to the init function with its argument
+pub type Trampoline = fn(*mut usize) -> !;
+
 /// the init function passed to reg_context
 #[inline]
-pub fn gen_init_impl(_: usize, f: *mut usize) -> ! {
+pub fn gen_init_impl(trampoline: usize, f: *mut usize) -> ! {
     overflow::init_once();
 
+    if trampoline != 0 {
+        let trampoline: Trampoline = unsafe { std::mem::transmute(trampoline) };
+        trampoline(f)
+    }
+
     let clo = move || {
         // consume self.f
         let f: &mut Option<Func> = unsafe { &mut *(f as *mut _) };
@@ -35,19 +45,28 @@ pub fn gen_init_impl(_: usize, f: *mut usize) -> ! {
         func.call_once();
     };
 
-    fn check_err(cause: Box<dyn Any + Send + 'static>) {
-        // this is not an error at all, ignore it
-        if let Some(Error::Cancel | Error::Done) = cause.downcast_ref::<Error>() {
-            return;
-        }
-
-        error!("set panic inside generator");
-        ContextStack::current().top().err = Some(cause);
-    }
-
     // we can't panic inside the generator context
     // need to propagate the panic to the main thread
-    if let Err(cause) = catch_unwind_filter(clo) {
+    if let Err(cause) = panic::catch_unwind(clo) {
+        check_err(cause);
+    }
+
+    exit()
+}
+
+/// finish the current generator without unwinding its stack
+/// the frames left on the generator stack are discarded without drop
+pub fn exit() -> ! {
+    // run the deferred cleanup no matter how the function is finished
+    // a panicking defer would not stop the rest from running
+    // the generator locals are dropped after that
+    let ctx = ContextStack::current().top();
+    while !ctx.defers.is_empty() {
+        if let Err(cause) = panic::catch_unwind(panic::AssertUnwindSafe(|| ctx.run_defers())) {
+            check_err(cause);
+        }
+    }
+    if let Err(cause) = panic::catch_unwind(panic::AssertUnwindSafe(|| ctx.drop_locals())) {
         check_err(cause);
     }
 
@@ -58,6 +58,12 @@ impl Registers {
             prefetch(ptr.add(8)); // SP + 8
         }
     }
+
+    /// the saved stack pointer
+    #[inline]
+    pub fn sp(&self) -> usize {
+        self.gpr[1]
+    }
 }
 
 pub fn initialize_call_frame(
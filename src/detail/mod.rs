@@ -35,6 +35,29 @@ mod gen;
 
 pub use self::asm::{gen_init, initialize_call_frame, swap_registers, InitFn, Registers};
 
+cfg_if::cfg_if! {
+    if #[cfg(all(
+        unix,
+        any(
+            all(target_arch = "x86_64", not(target_os = "macos")),
+            all(target_arch = "aarch64", not(target_vendor = "apple"))
+        )
+    ))] {
+        pub use self::asm::set_resumer;
+
+        /// the unwinders go on from the generator stack into the resumer
+        pub const STITCHED: bool = true;
+    } else {
+        /// the frames are not stitched on this platform
+        #[inline]
+        pub fn set_resumer(_stack_end: usize, _regs: &Registers) {}
+
+        /// the unwinders stop at the generator stack bottom
+        pub const STITCHED: bool = false;
+    }
+}
+pub use self::gen::{exit, Trampoline};
+
 #[inline]
 fn align_down(sp: *mut usize) -> *mut usize {
     let sp = (sp as usize) & !(16 - 1);
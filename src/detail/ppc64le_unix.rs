@@ -64,6 +64,12 @@ impl Registers {
             prefetch(&self.gpr[0]);
         }
     }
+
+    /// the saved stack pointer
+    #[inline]
+    pub fn sp(&self) -> usize {
+        self.gpr[REG_FP]
+    }
 }
 
 pub fn initialize_call_frame(
@@ -39,6 +39,12 @@ impl Registers {
             prefetch(ptr.add(1)); // SP + 8
         }
     }
+
+    /// the saved stack pointer
+    #[inline]
+    pub fn sp(&self) -> usize {
+        self.gpr[12]
+    }
 }
 
 pub fn initialize_call_frame(
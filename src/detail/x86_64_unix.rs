@@ -124,6 +124,12 @@ impl Registers {
             prefetch(ptr.add(8)); // RSP + 8
         }
     }
+
+    /// the saved stack pointer
+    #[inline]
+    pub fn sp(&self) -> usize {
+        self.gpr[1]
+    }
 }
 
 pub fn initialize_call_frame(
@@ -148,16 +154,54 @@ pub fn initialize_call_frame(
     regs.gpr[RUSTRT_R13] = arg2 as usize;
     regs.gpr[RUSTRT_R14] = fptr as usize;
 
-    // Last base pointer on the stack should be 0
-    regs.gpr[RUSTRT_RBP] = 0;
+    // the last base pointer is the frame record at the stack bottom
+    regs.gpr[RUSTRT_RBP] = mut_offset(sp, -2) as usize;
 
     // setup the init stack
     // this is prepared for the swap context
-    regs.gpr[RUSTRT_RSP] = mut_offset(sp, -2) as usize;
+    regs.gpr[RUSTRT_RSP] = mut_offset(sp, -6) as usize;
 
     unsafe {
         // leave enough space for RET
-        *mut_offset(sp, -2) = bootstrap_green_task as *const () as usize;
-        *mut_offset(sp, -1) = 0;
+        *mut_offset(sp, -6) = bootstrap_green_task as *const () as usize;
+        // the return address of the init function
+        *mut_offset(sp, -5) = stitch_ret();
+        // the resumer registers, see `set_resumer`
+        *mut_offset(sp, -4) = 0;
+        *mut_offset(sp, -3) = 0;
+        // the frame record linked to the resumer, see `set_resumer`
+        *mut_offset(sp, -2) = 0;
+        *mut_offset(sp, -1) = stitch_ret();
+    }
+}
+
+/// record the registers of the resumer at the stack bottom
+///
+/// the unwinder continues from the generator init frame into the resumer,
+/// the frame pointer walkers go on by the saved rbp and the return address
+/// that `swap_registers` puts next to it
+#[cfg(not(target_os = "macos"))]
+#[inline]
+pub fn set_resumer(stack_end: usize, regs: &Registers) {
+    let sp = align_down(stack_end as *mut usize);
+    unsafe {
+        *mut_offset(sp, -4) = regs as *const _ as usize;
+        *mut_offset(sp, -2) = &regs.gpr[2] as *const _ as usize;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "macos")] {
+        // no unwind info to stitch the frames
+        fn stitch_ret() -> usize {
+            0
+        }
+    } else {
+        fn stitch_ret() -> usize {
+            extern "sysv64" {
+                fn generator_stitch_ret();
+            }
+            generator_stitch_ret as *const () as usize
+        }
     }
 }
@@ -3,22 +3,39 @@
 //! Rust generator implementation
 //!
 
-use crate::detail::gen_init;
+use crate::detail::{gen_init, set_resumer, Registers, Trampoline, STITCHED};
+use crate::gen_panic::{default_panic_report, GeneratorPanic, PanicReport};
+use crate::observer::{default_observer, GeneratorObserver, Observed};
 use crate::reg_context::RegContext;
-use crate::rt::{Context, ContextStack, Error};
+use crate::registry;
+use crate::rt::{default_cancel_policy, CancelPolicy, Context, ContextStack, Error, GeneratorId};
 use crate::scope::Scope;
 use crate::stack::{Func, Stack, StackBox};
+use crate::stats::{self, GeneratorStats, StatsCell};
 
 use std::any::Any;
+use std::backtrace::Backtrace;
 use std::fmt;
 use std::marker::PhantomData;
-use std::panic;
+use std::panic::{self, Location};
+use std::ptr;
+use std::sync::Arc;
 use std::thread;
 
 /// The default stack size for generators, in bytes.
+///
+/// 1/16 of every generator stack, at most 256 words, is reserved for the
+/// `Scope::defer` functions, the frames only get the rest
 // windows has a minimal size as 0x4a8!!!!
 pub const DEFAULT_STACK_SIZE: usize = 0x1000;
 
+// the max words reserved on the generator stack for the `Scope::defer` functions,
+// it's split off when the generator is created since the frames are right below
+const DEFER_AREA: usize = 0x100;
+
+// the words left on the generator stack that `backtrace` needs to walk it
+const BACKTRACE_STACK: usize = 0x400;
+
 #[inline]
 #[cold]
 fn cold() {}
@@ -37,6 +54,42 @@ pub(crate) fn unlikely(b: bool) -> bool {
     b
 }
 
+/// finish the discarded generator, see `GeneratorImpl::discard`
+fn discard_entry(_: *mut usize) -> ! {
+    crate::detail::exit()
+}
+
+/// the capture of a suspended generator backtrace, see `GeneratorImpl::backtrace`
+struct Trace {
+    regs: RegContext,
+    back: RegContext,
+    backtrace: Option<Backtrace>,
+}
+
+fn trace_entry(p: *mut usize) -> ! {
+    let trace = unsafe { &mut *(p as *mut Trace) };
+    trace.backtrace = Some(Backtrace::force_capture());
+    RegContext::swap(&mut trace.regs, &trace.back);
+    unreachable!("the trace is never resumed")
+}
+
+/// the state of a generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorState {
+    /// created but not started yet
+    Created,
+    /// yielded and waiting for the next resume
+    Suspended,
+    /// running on the current thread
+    Running,
+    /// finished by return or `done!()`
+    Completed,
+    /// finished by cancel
+    Cancelled,
+    /// finished by a panic
+    Panicked,
+}
+
 /// the generator obj type, the functor passed to it must be Send
 pub struct GeneratorObj<'a, A, T, const LOCAL: bool> {
     gen: StackBox<GeneratorImpl<'a, A, T>>,
@@ -72,6 +125,13 @@ impl<'a, A, T> Generator<'a, A, T> {
 /// the local generator type, can't Send
 pub type LocalGenerator<'a, A, T> = GeneratorObj<'a, A, T, true>;
 
+impl<'a, A, T> Generator<'a, A, T> {
+    /// the same generator that is not `Send` any more
+    pub(crate) fn into_local(self) -> LocalGenerator<'a, A, T> {
+        GeneratorObj { gen: self.gen }
+    }
+}
+
 impl<'a, A, T> LocalGenerator<'a, A, T> {
     /// init a heap based generator with scoped closure
     pub fn scoped_init<F>(&mut self, f: F)
@@ -85,6 +145,36 @@ impl<'a, A, T> LocalGenerator<'a, A, T> {
 }
 
 impl<'a, A, T, const LOCAL: bool> GeneratorObj<'a, A, T, LOCAL> {
+    /// create a scoped generator whose closure returns `None` to finish
+    /// without checking the `Send` bound, the caller must make sure that
+    /// a non local generator only captures sendable data
+    #[track_caller]
+    pub(crate) fn new_scoped_done_unchecked<F>(size: usize, f: F) -> Self
+    where
+        for<'scope> F: FnOnce(Scope<'scope, 'a, A, T>) -> Option<T> + 'a,
+        T: 'a,
+        A: 'a,
+    {
+        let mut gen = GeneratorImpl::<A, T>::new(Stack::new(size));
+        gen.scoped_init_done(f);
+        GeneratorObj { gen }
+    }
+
+    /// create a generator for the raw `yield_with` api without checking
+    /// the `Send` bound, see `new_scoped_done_unchecked`
+    #[track_caller]
+    pub(crate) fn new_opt_unchecked<F>(size: usize, f: F) -> Self
+    where
+        F: FnOnce() -> T + 'a,
+        A: Any,
+        T: Any,
+    {
+        let mut gen = GeneratorImpl::<A, T>::new(Stack::new(size));
+        gen.init_context();
+        gen.init_code(f);
+        GeneratorObj { gen }
+    }
+
     /// Constructs a Generator from a raw pointer.
     ///
     /// # Safety
@@ -161,16 +251,144 @@ impl<'a, A, T, const LOCAL: bool> GeneratorObj<'a, A, T, LOCAL> {
         self.gen.cancel()
     }
 
+    /// close the generator gracefully
+    ///
+    /// the generator is resumed once with a `None` para while `Scope::is_closing`
+    /// returns true, so that it can do the cleanup and return a last value.
+    /// if it yields again instead of finishing, the yielded value is returned
+    /// and the generator is cancelled the same way as `cancel`
+    pub fn close(&mut self) -> Option<T> {
+        self.gen.close()
+    }
+
+    /// set how the generator is cancelled when it's dropped or `cancel` is called
+    ///
+    /// # Safety
+    ///
+    /// with `Leak` or `Defer` the suspended frames are discarded without drop,
+    /// the generator must not rely on the drop of its locals across a yield for
+    /// soundness, e.g. a guard borrowed by a `scoped` thread or a pinned value
+    #[inline]
+    pub unsafe fn set_cancel_policy(&mut self, policy: CancelPolicy) {
+        self.gen.cancel_policy = policy;
+    }
+
+    /// get the cancel policy of the generator
+    #[inline]
+    pub fn cancel_policy(&self) -> CancelPolicy {
+        self.gen.cancel_policy
+    }
+
+    /// set how the panics inside the generator are reported
+    #[inline]
+    pub fn set_panic_report(&mut self, report: PanicReport) {
+        self.gen.context.panic_report = report;
+    }
+
+    /// get the panic report policy of the generator
+    #[inline]
+    pub fn panic_report(&self) -> PanicReport {
+        self.gen.context.panic_report.clone()
+    }
+
+    /// start recording the stats of the generator, see `stats::enable`
+    pub fn enable_stats(&mut self) {
+        let ctx = &mut self.gen.context;
+        if ctx.stats.is_none() {
+            let stats = Arc::<StatsCell>::default();
+            if let Some(entry) = ctx.entry.as_ref() {
+                entry.set_stats(stats.clone());
+            }
+            ctx.stats = Some(stats);
+            ctx.update_hooked();
+        }
+    }
+
+    /// get the stats of the generator, `None` if they are not recorded
+    pub fn stats(&self) -> Option<GeneratorStats> {
+        self.gen.context.stats.as_ref().map(|s| s.get())
+    }
+
+    /// set the observer of the generator events, it replaces the default one
+    ///
+    /// the `on_drop` of the removed observer is not called
+    pub fn set_observer(&mut self, observer: Option<Arc<dyn GeneratorObserver>>) {
+        let id = self.id();
+        let ctx = &mut self.gen.context;
+        match (ctx.observer.as_mut(), observer) {
+            (Some(o), Some(observer)) => o.observer = observer,
+            (None, Some(observer)) => ctx.observer = Some(Observed::new(observer, id)),
+            (_, None) => {
+                if let Some(o) = ctx.observer.take() {
+                    o.detach();
+                }
+            }
+        }
+        ctx.update_hooked();
+    }
+
     /// is finished
     #[inline]
     pub fn is_done(&self) -> bool {
         self.gen.is_done()
     }
 
+    /// the context used as the delegate of `yield_from`
+    #[inline]
+    pub(crate) fn as_delegate(&mut self) -> *mut Context {
+        // the context is the first field of the repr(C) generator
+        self.gen.as_ptr() as *mut Context
+    }
+
+    /// get the unique id of the generator
+    #[inline]
+    pub fn id(&self) -> GeneratorId {
+        self.gen.id()
+    }
+
+    /// set the generator name, it's used in the panic and stack overflow messages
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        let name = name.into();
+        if let Some(entry) = self.gen.context.entry.as_ref() {
+            entry.set_name(Some(name.clone()));
+        }
+        self.gen.context.name = Some(name);
+    }
+
+    /// get the generator name
+    #[inline]
+    pub fn name(&self) -> Option<&str> {
+        self.gen.context.name.as_deref()
+    }
+
+    /// get the state of the generator
+    pub fn state(&self) -> GeneratorState {
+        self.gen.state()
+    }
+
+    /// is running on the current context stack
+    #[inline]
+    pub(crate) fn is_running(&self) -> bool {
+        self.gen.is_started() && !self.gen.is_done() && self.gen.context.is_generator()
+    }
+
     /// get stack total size and used size in word
     pub fn stack_usage(&self) -> (usize, usize) {
         self.gen.stack_usage()
     }
+
+    /// capture the backtrace of the frames where the generator is suspended
+    ///
+    /// the capture runs on the generator stack below the suspended frames and
+    /// walks up through them, nothing of the generator runs. returns `None` if
+    /// it's not suspended, or there is not enough stack left below the
+    /// suspension point to do the capture. it's always `None` on the platforms
+    /// that can't unwind across the generator stack, only the x86_64 and
+    /// aarch64 unix targets except the apple ones can. when it's delegating by
+    /// `yield_from` the frames end in the `yield_from`
+    pub fn backtrace(&mut self) -> Option<Backtrace> {
+        self.gen.backtrace()
+    }
 }
 
 impl<T, const LOCAL: bool> Iterator for GeneratorObj<'_, (), T, LOCAL> {
@@ -182,13 +400,19 @@ impl<T, const LOCAL: bool> Iterator for GeneratorObj<'_, (), T, LOCAL> {
 
 impl<A, T, const LOCAL: bool> fmt::Debug for GeneratorObj<'_, A, T, LOCAL> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Generator<{}, Output={}, Local={}> {{ ... }}",
+        let name = format!(
+            "Generator<{}, Output={}, Local={}>",
             std::any::type_name::<A>(),
             std::any::type_name::<T>(),
             LOCAL
-        )
+        );
+        f.debug_struct(&name)
+            .field("id", &self.gen.context.id)
+            .field("name", &self.name())
+            .field("state", &self.state())
+            .field("stack_usage", &self.stack_usage())
+            .field("depth", &self.gen.delegate_depth())
+            .finish()
     }
 }
 
@@ -199,6 +423,7 @@ pub struct Gn<A = ()> {
 
 impl<A> Gn<A> {
     /// create a scoped generator with default stack size
+    #[track_caller]
     pub fn new_scoped<'a, T, F>(f: F) -> Generator<'a, A, T>
     where
         for<'scope> F: FnOnce(Scope<'scope, 'a, A, T>) -> T + Send + 'a,
@@ -209,6 +434,7 @@ impl<A> Gn<A> {
     }
 
     /// create a scoped local generator with default stack size
+    #[track_caller]
     pub fn new_scoped_local<'a, T, F>(f: F) -> LocalGenerator<'a, A, T>
     where
         F: FnOnce(Scope<A, T>) -> T + 'a,
@@ -219,6 +445,9 @@ impl<A> Gn<A> {
     }
 
     /// create a scoped generator with specified stack size
+    ///
+    /// 1/16 of the stack, at most 256 words, is reserved for `Scope::defer`
+    #[track_caller]
     pub fn new_scoped_opt<'a, T, F>(size: usize, f: F) -> Generator<'a, A, T>
     where
         for<'scope> F: FnOnce(Scope<'scope, 'a, A, T>) -> T + Send + 'a,
@@ -231,6 +460,7 @@ impl<A> Gn<A> {
     }
 
     /// create a scoped local generator with specified stack size
+    #[track_caller]
     pub fn new_scoped_opt_local<'a, T, F>(size: usize, f: F) -> LocalGenerator<'a, A, T>
     where
         F: FnOnce(Scope<A, T>) -> T + 'a,
@@ -241,12 +471,44 @@ impl<A> Gn<A> {
         gen.scoped_init(f);
         LocalGenerator { gen }
     }
+
+    /// create a scoped generator with default stack size
+    /// whose closure returns `None` to finish without a value
+    ///
+    /// this is the panic free counterpart of `done!()`, the generator
+    /// is finished through the normal closure return
+    #[track_caller]
+    pub fn new_scoped_done<'a, T, F>(f: F) -> Generator<'a, A, T>
+    where
+        for<'scope> F: FnOnce(Scope<'scope, 'a, A, T>) -> Option<T> + Send + 'a,
+        T: Send + 'a,
+        A: Send + 'a,
+    {
+        let mut gen = GeneratorImpl::<A, T>::new(Stack::new(DEFAULT_STACK_SIZE));
+        gen.scoped_init_done(f);
+        Generator { gen }
+    }
+
+    /// create a scoped local generator with default stack size
+    /// whose closure returns `None` to finish without a value
+    #[track_caller]
+    pub fn new_scoped_done_local<'a, T, F>(f: F) -> LocalGenerator<'a, A, T>
+    where
+        F: FnOnce(Scope<A, T>) -> Option<T> + 'a,
+        T: 'a,
+        A: 'a,
+    {
+        let mut gen = GeneratorImpl::<A, T>::new(Stack::new(DEFAULT_STACK_SIZE));
+        gen.scoped_init_done(f);
+        LocalGenerator { gen }
+    }
 }
 
 impl<A: Any> Gn<A> {
     /// create a new generator with default stack size
     #[allow(clippy::new_ret_no_self)]
     #[deprecated(since = "0.6.18", note = "please use `scope` version instead")]
+    #[track_caller]
     pub fn new<'a, T: Any, F>(f: F) -> Generator<'a, A, T>
     where
         F: FnOnce() -> T + Send + 'a,
@@ -255,7 +517,10 @@ impl<A: Any> Gn<A> {
     }
 
     /// create a new generator with specified stack size
+    ///
+    /// 1/16 of the stack, at most 256 words, is reserved for `Scope::defer`
     // the `may` library use this API so we can't deprecated it yet.
+    #[track_caller]
     pub fn new_opt<'a, T: Any, F>(size: usize, f: F) -> Generator<'a, A, T>
     where
         F: FnOnce() -> T + Send + 'a,
@@ -280,6 +545,10 @@ struct GeneratorImpl<'a, A, T> {
     ret: Option<T>,
     // boxed functor
     f: Option<Func>,
+    // the stack box usage before the functor is allocated
+    base_offset: usize,
+    // how to cancel the generator
+    cancel_policy: CancelPolicy,
     // phantom lifetime
     phantom: PhantomData<&'a T>,
 }
@@ -299,19 +568,41 @@ impl<A: Any, T: Any> GeneratorImpl<'_, A, T> {
 
 impl<'a, A, T> GeneratorImpl<'a, A, T> {
     /// create a new generator with specified stack size
+    #[track_caller]
     fn new(mut stack: Stack) -> StackBox<Self> {
         // the stack box would finally dealloc the stack!
         unsafe {
             let mut stack_box = stack.alloc_uninit_box::<GeneratorImpl<'a, A, T>>();
+            // the defer area is above the frames, it's kept for the re-init
+            let defer_area = stack.split_off(usize::min(DEFER_AREA, stack.size() / 16));
+            let base_offset = stack.offset();
             (*stack_box.as_mut_ptr()).init(GeneratorImpl {
                 para: None,
                 stack,
                 ret: None,
                 f: None,
+                base_offset,
+                cancel_policy: default_cancel_policy(),
                 context: Context::new(),
                 phantom: PhantomData,
             });
-            stack_box.assume_init()
+            let mut gen = stack_box.assume_init();
+            gen.context.defer_area = Some(defer_area);
+            gen.context.id = GeneratorId::next().0;
+            gen.context.panic_report = default_panic_report();
+            if let Some(observer) = default_observer() {
+                let id = gen.id();
+                observer.on_create(id);
+                gen.context.observer = Some(Observed::new(observer, id));
+            }
+            let size = gen.stack.size();
+            if stats::is_enabled() {
+                gen.context.stats = Some(Arc::default());
+            }
+            let stats = gen.context.stats.clone();
+            gen.context.entry = registry::register(gen.context.id, Location::caller(), size, stats);
+            gen.context.update_hooked();
+            gen
         }
     }
 
@@ -327,15 +618,35 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
         for<'scope> F: FnOnce(Scope<'scope, 'a, A, T>) -> T + 'a,
         T: 'a,
         A: 'a,
+    {
+        self.scoped_init_done(move |s| Some(f(s)));
+    }
+
+    /// init a heap based generator with scoped closure
+    /// the closure returns `None` to finish without a value
+    fn scoped_init_done<F>(&mut self, f: F)
+    where
+        for<'scope> F: FnOnce(Scope<'scope, 'a, A, T>) -> Option<T> + 'a,
+        T: 'a,
+        A: 'a,
     {
         use std::mem::transmute;
         let scope: Scope<A, T> = unsafe { transmute(Scope::new(&mut self.para, &mut self.ret)) };
-        self.init_code(move || f(scope));
+        self.init_code_done(move || f(scope));
     }
 
     /// init a heap based generator
     // it's can be used to re-init a 'done' generator before it's get dropped
     fn init_code<F: FnOnce() -> T + 'a>(&mut self, f: F)
+    where
+        T: 'a,
+    {
+        self.init_code_done(move || Some(f()));
+    }
+
+    /// init a heap based generator
+    /// the returned `None` is the marker that finishes the generator without a value
+    fn init_code_done<F: FnOnce() -> Option<T> + 'a>(&mut self, f: F)
     where
         T: 'a,
     {
@@ -351,11 +662,19 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
 
         // init the ref to 0 means that it's ready to start
         self.context._ref = 0;
+        self.context.closing = false;
+        self.context.delegate = std::ptr::null_mut();
+        self.context.delegator = std::ptr::null_mut();
+        self.context.resumer = 0;
+        self.context.defers.clear();
+        self.context.locals.clear();
         let ret = &mut self.ret as *mut _;
+        // the functor of a discarded run is leaked on the stack, reclaim it
+        unsafe { self.stack.reset_offset(self.base_offset) };
         // alloc the function on stack
         let func = StackBox::new_fn_once(&mut self.stack, move || {
             let r = f();
-            unsafe { *ret = Some(r) };
+            unsafe { *ret = r };
         });
 
         self.f = Some(func);
@@ -373,6 +692,59 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
     /// resume the generator
     #[inline]
     fn resume_gen(&mut self) {
+        self.swap_in();
+        self.propagate_err();
+    }
+
+    /// switch to the generator until it yields back
+    #[inline]
+    fn swap_in(&mut self) {
+        if unlikely(self.context.hooked) {
+            return self.swap_in_hooked();
+        }
+        self.switch_in();
+    }
+
+    /// `swap_in` through the hooks
+    #[cold]
+    #[inline(never)]
+    fn swap_in_hooked(&mut self) {
+        let cpu_start = self.before_switch();
+        self.switch_in();
+        self.after_switch(cpu_start);
+    }
+
+    /// call the hooks before the generator is resumed, the cpu time is
+    /// returned if the stats are recorded
+    fn before_switch(&mut self) -> Option<u64> {
+        if let Some(entry) = self.context.entry.as_ref() {
+            entry.on_resume();
+            if registry::is_watched() {
+                registry::on_switch(Some(entry));
+            }
+        }
+        self.observe_resume();
+        self.context.stats.as_ref().map(|stats| stats.on_resume())
+    }
+
+    /// call the hooks after the generator is switched back
+    fn after_switch(&mut self, cpu_start: Option<u64>) {
+        if let Some(entry) = self.context.entry.as_ref() {
+            if registry::is_watched() {
+                registry::on_switch(ContextStack::current().top().entry.as_ref());
+            }
+            entry.set_state(self.state());
+        }
+        if let (Some(start), Some(stats)) = (cpu_start, self.context.stats.as_ref()) {
+            let yielded = self.context._ref & 0x3 == 0;
+            stats.on_switch_back(start, yielded);
+        }
+        self.observe_switch_back();
+    }
+
+    /// switch to the generator without the hooks
+    #[inline]
+    fn switch_in(&mut self) {
         let env = ContextStack::current();
         // get the current regs
         let cur = &mut env.top().regs;
@@ -383,25 +755,136 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
         debug_assert!(!self.context.parent.is_null());
         let top = unsafe { &mut *self.context.parent };
 
+        // let the unwinder go from the generator stack into the current one,
+        // the link is only written when it's resumed by another context
+        let resumer = &cur.regs as *const Registers as usize;
+        if STITCHED && unlikely(top.resumer != resumer) {
+            top.resumer = resumer;
+            set_resumer(top.stack_guard.1, &cur.regs);
+        }
+
         // save current generator context on stack
         env.push_context(&mut self.context);
 
         // swap to the generator
         RegContext::swap(cur, &top.regs);
+    }
 
-        // comes back, check the panic status
+    fn observe_resume(&mut self) {
+        // the cancel is reported when it's switched back
+        if self.context._ref == 2 {
+            return;
+        }
+        if let Some(o) = self.context.observer.as_mut() {
+            o.resume();
+        }
+    }
+
+    fn observe_switch_back(&mut self) {
+        let state = self.state();
+        let Some(o) = self.context.observer.as_ref() else {
+            return;
+        };
+        let (id, observer) = (o.id(), &o.observer);
+        match state {
+            GeneratorState::Suspended => observer.on_yield(id, o.elapsed()),
+            GeneratorState::Completed => observer.on_complete(id, o.elapsed()),
+            GeneratorState::Cancelled => observer.on_cancel(id),
+            GeneratorState::Panicked => observer.on_panic(id, o.elapsed()),
+            GeneratorState::Created | GeneratorState::Running => {}
+        }
+    }
+
+    /// comes back, check the panic status
+    #[inline]
+    fn propagate_err(&mut self) {
         // this would propagate the panic until root context
         // if it's a coroutine just stop propagate
         if !self.context.local_data.is_null() {
             return;
         }
 
-        if let Some(err) = self.context.err.take() {
-            // pass the error to the parent until root
-            panic::resume_unwind(err);
+        if unlikely(self.context.err.is_some()) {
+            self.raise_err();
         }
     }
 
+    /// pass the panic of the generator to the parent until root
+    #[cold]
+    #[inline(never)]
+    fn raise_err(&mut self) -> ! {
+        let err = self.context.err.take().unwrap();
+        if !ContextStack::current().top().is_generator() {
+            GeneratorPanic::report_root(&*err);
+        }
+        panic::resume_unwind(err)
+    }
+
+    /// resume the generator with the para already set, the panic is not propagated
+    #[inline]
+    fn resume_delegate(&mut self) {
+        if unlikely(self.context.hooked || !self.context.delegate.is_null()) {
+            return self.resume_slow();
+        }
+
+        // every time we call the function, increase the ref count
+        // yield will decrease it and return will not
+        self.context._ref += 1;
+        self.switch_in();
+    }
+
+    /// `resume_delegate` through the hooks or the `yield_from` delegates
+    ///
+    /// when the generator is delegating by `yield_from`, the innermost delegate
+    /// is resumed directly without switching through the intermediate frames.
+    /// the delegator is only woken up when its delegate is finished, its hooks
+    /// see the resume of the delegate as its own
+    #[cold]
+    #[inline(never)]
+    fn resume_slow(&mut self) {
+        let hooked = self.context.hooked;
+        let cpu_start = if hooked { self.before_switch() } else { None };
+        if self.context.delegate.is_null() || !self.resume_innermost() {
+            self.context._ref += 1;
+            self.switch_in();
+        }
+        if hooked {
+            self.after_switch(cpu_start);
+        }
+    }
+
+    /// resume the innermost delegate, returns false if the generator itself
+    /// needs to be resumed since all the delegates are finished
+    fn resume_innermost(&mut self) -> bool {
+        let this: *mut Context = &mut self.context;
+        let mut d = self.context.delegate;
+        // the delegate may delegate further since the last resume
+        unsafe {
+            while !(*d).delegate.is_null() {
+                d = (*d).delegate;
+            }
+        }
+        while d != this {
+            // all the generators in the chain have the same type, see `yield_from`
+            let g = unsafe { &mut *(d as *mut Self) };
+            if !g.is_done() {
+                g.para = self.para.take();
+                g.context._ref += 1;
+                g.swap_in();
+                // a finished delegate with no value or a panic need the delegator to handle it
+                if g.context.err.is_none() && (!g.is_done() || g.ret.is_some()) {
+                    self.ret = g.ret.take();
+                    // cache the innermost one for the next resume
+                    self.context.delegate = d;
+                    return true;
+                }
+            }
+            d = g.context.delegator;
+            unsafe { (*d).delegate = std::ptr::null_mut() };
+        }
+        false
+    }
+
     #[inline]
     fn is_started(&self) -> bool {
         // when the f is consumed we think it's running
@@ -440,10 +923,8 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
             return None;
         }
 
-        // every time we call the function, increase the ref count
-        // yield will decrease it and return will not
-        self.context._ref += 1;
-        self.resume_gen();
+        self.resume_delegate();
+        self.propagate_err();
 
         self.ret.take()
     }
@@ -459,10 +940,8 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
         // the yield part would read out this value in the next round
         self.para = para;
 
-        // every time we call the function, increase the ref count
-        // yield will decrease it and return will not
-        self.context._ref += 1;
-        self.resume_gen();
+        self.resume_delegate();
+        self.propagate_err();
 
         self.ret.take()
     }
@@ -476,14 +955,98 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
     /// cancel the generator without any check
     #[inline]
     fn raw_cancel(&mut self) {
-        // tell the func to panic
-        // so that we can stop the inner func
+        // the delegate is cancelled when the generator unwinds
+        if self.cancel_policy != CancelPolicy::Unwind {
+            self.discard_delegates();
+        }
+        self.context.delegate = std::ptr::null_mut();
+        match self.cancel_policy {
+            CancelPolicy::Unwind => {
+                // tell the func to panic
+                // so that we can stop the inner func
+                self.context._ref = 2;
+                // the Cancel is raised without calling the panic hook
+                self.resume_gen();
+            }
+            CancelPolicy::Leak => {
+                self.context.defers.clear();
+                self.discard();
+            }
+            CancelPolicy::Defer => self.discard(),
+        }
+    }
+
+    /// finish the suspended generator without unwinding, the frames on the
+    /// generator stack are leaked
+    ///
+    /// it's switched in to run the defers and drop the locals in the generator
+    /// context by `exit`, a panic there is reported but not propagated since
+    /// it may be in the drop
+    fn discard(&mut self) {
+        self.mark_cancelled();
+        // resume at `exit` on the stack below the suspended frames
+        let top = unsafe { &mut *self.context.parent };
+        let area = unsafe { Stack::below(top.regs.sp(), top.stack_guard.0) };
+        top.regs.init_with(
+            gen_init,
+            discard_entry as Trampoline as usize,
+            ptr::null_mut(),
+            &area,
+        );
+        // the unwinder must not walk into an empty resumer
+        set_resumer(
+            area.end() as usize,
+            &ContextStack::current().top().regs.regs,
+        );
+        self.switch_in();
+        if let Some(err) = self.context.err.take() {
+            GeneratorPanic::report_root(&*err);
+            self.context._ref = 2;
+        }
+    }
+
+    /// discard the generators delegated by `yield_from` from the innermost one,
+    /// they are never dropped since they are in the leaked frames, so they are
+    /// removed from the registry and the observer here. their stacks are leaked
+    fn discard_delegates(&mut self) {
+        let this: *mut Context = &mut self.context;
+        let mut d = self.context.delegate;
+        if d.is_null() {
+            return;
+        }
+        unsafe {
+            while !(*d).delegate.is_null() {
+                d = (*d).delegate;
+            }
+        }
+        while d != this {
+            // all the generators in the chain have the same type, see `yield_from`
+            let g = unsafe { &mut *(d as *mut Self) };
+            d = g.context.delegator;
+            g.context.delegate = std::ptr::null_mut();
+            if !g.is_done() {
+                if self.cancel_policy == CancelPolicy::Leak {
+                    g.context.defers.clear();
+                }
+                g.discard();
+            }
+            if let Some(entry) = g.context.entry.take() {
+                registry::deregister(&entry);
+            }
+            // the `on_drop` is called
+            drop(g.context.observer.take());
+        }
+    }
+
+    /// set the cancelled state without resuming the generator
+    fn mark_cancelled(&mut self) {
         self.context._ref = 2;
-        // save the old panic hook, we don't want to print anything for the Cancel
-        let old = panic::take_hook();
-        panic::set_hook(Box::new(|_| {}));
-        self.resume_gen();
-        panic::set_hook(old);
+        if let Some(entry) = self.context.entry.as_ref() {
+            entry.set_state(GeneratorState::Cancelled);
+        }
+        if let Some(o) = self.context.observer.as_ref() {
+            o.observer.on_cancel(o.id());
+        }
     }
 
     /// cancel the generator
@@ -496,12 +1059,114 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
         // consume the fun if it's not started
         if !self.is_started() {
             self.f.take();
-            self.context._ref = 1;
+            self.mark_cancelled();
         } else {
             self.raw_cancel();
         }
     }
 
+    /// close the generator
+    /// resume it with the closing flag set and cancel it if it's still not finished
+    fn close(&mut self) -> Option<T> {
+        if self.is_done() {
+            return None;
+        }
+
+        // no need to resume a generator that is not started
+        if !self.is_started() {
+            self.cancel();
+            return None;
+        }
+
+        self.context.closing = true;
+        let ret = self.raw_send(None);
+        if !self.is_done() {
+            trace!("generator is not done while close");
+            self.raw_cancel();
+        }
+        ret
+    }
+
+    /// get the unique id of the generator
+    #[inline]
+    fn id(&self) -> GeneratorId {
+        GeneratorId(self.context.id)
+    }
+
+    /// get the state from the ref count and the functor
+    fn state(&self) -> GeneratorState {
+        if !self.is_started() {
+            return GeneratorState::Created;
+        }
+        match self.context._ref & 0x3 {
+            0 => GeneratorState::Suspended,
+            // the ref is 1 when it's running or returned
+            1 if self.context.is_generator() => GeneratorState::Running,
+            1 => GeneratorState::Completed,
+            2 => GeneratorState::Cancelled,
+            _ => GeneratorState::Panicked,
+        }
+    }
+
+    /// capture the backtrace of the suspended frames
+    fn backtrace(&mut self) -> Option<Backtrace> {
+        // coroutines are suspended by `co_yield_with` that can't be traced
+        if !STITCHED
+            || self.state() != GeneratorState::Suspended
+            || !self.context.local_data.is_null()
+        {
+            return None;
+        }
+        // the capture runs on the generator stack below the suspended frames
+        let sp = self.context.regs.sp();
+        if sp - self.context.stack_guard.0 < BACKTRACE_STACK * std::mem::size_of::<usize>() {
+            return None;
+        }
+        let mut trace = Trace {
+            regs: RegContext::empty(),
+            back: RegContext::empty(),
+            backtrace: None,
+        };
+        let p = &mut trace as *mut Trace;
+        unsafe {
+            let area = Stack::below(sp, self.context.stack_guard.0);
+            (*p).regs.init_with(
+                gen_init,
+                trace_entry as Trampoline as usize,
+                p as *mut usize,
+                &area,
+            );
+            // walk from the capture into the suspended frames and then back here,
+            // nothing of the generator runs
+            set_resumer(area.end() as usize, &self.context.regs.regs);
+            set_resumer(self.context.stack_guard.1, &(*p).back.regs);
+            self.context.resumer = 0;
+            RegContext::swap(&mut (*p).back, &(*p).regs);
+        }
+        trace.backtrace
+    }
+
+    /// the number of generators it's delegating to by `yield_from`
+    fn delegate_depth(&self) -> usize {
+        let this = &self.context as *const Context;
+        let mut d = self.context.delegate as *const Context;
+        if d.is_null() {
+            return 0;
+        }
+        // walk down to the innermost and then count back to self
+        unsafe {
+            while !(*d).delegate.is_null() {
+                d = (*d).delegate;
+            }
+            let mut depth = 0;
+            while d != this {
+                d = (*d).delegator;
+                depth += 1;
+            }
+            depth
+        }
+    }
+
     /// is finished
     #[inline]
     fn is_done(&self) -> bool {
@@ -516,6 +1181,10 @@ impl<'a, A, T> GeneratorImpl<'a, A, T> {
 
 impl<A, T> Drop for GeneratorImpl<'_, A, T> {
     fn drop(&mut self) {
+        if let Some(entry) = self.context.entry.take() {
+            registry::deregister(&entry);
+        }
+
         // when the thread is already panic, do nothing
         if thread::panicking() {
             return;
@@ -0,0 +1,320 @@
+//! # generator panic
+//!
+//! the payload re-thrown to the resumer when a generator panics
+//!
+
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+use std::fmt;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use crate::rt::{current_generator, Context, GeneratorId};
+
+/// how the panics inside a generator are reported
+///
+/// the panic hook of `install_panic_hook` is needed to keep the panic from
+/// being printed at the site with the policies other than `Site`
+#[derive(Clone, Default)]
+pub enum PanicReport {
+    /// print by the panic hook at the panic site
+    #[default]
+    Site,
+    /// print the `GeneratorPanic` when it's propagated out to a thread root context
+    Root,
+    /// pass the `GeneratorPanic` to the callback, nothing is printed
+    Callback(Arc<dyn Fn(&GeneratorPanic) + Send + Sync>),
+    /// report nothing, the panic is still propagated to the resumer
+    Silent,
+}
+
+impl fmt::Debug for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PanicReport::Site => f.pad("Site"),
+            PanicReport::Root => f.pad("Root"),
+            PanicReport::Callback(_) => f.pad("Callback(..)"),
+            PanicReport::Silent => f.pad("Silent"),
+        }
+    }
+}
+
+// false means not set, use the `PanicReport::default()`
+static PANIC_REPORT_SET: AtomicBool = AtomicBool::new(false);
+static PANIC_REPORT: RwLock<PanicReport> = RwLock::new(PanicReport::Site);
+
+/// set the panic report policy for the generators created afterwards
+pub fn set_default_panic_report(report: PanicReport) {
+    *PANIC_REPORT.write().unwrap() = report;
+    PANIC_REPORT_SET.store(true, Ordering::Release);
+}
+
+/// get the panic report policy for new generators
+pub fn default_panic_report() -> PanicReport {
+    if !PANIC_REPORT_SET.load(Ordering::Acquire) {
+        return PanicReport::default();
+    }
+    PANIC_REPORT.read().unwrap().clone()
+}
+
+type Hook = Box<dyn Fn(&panic::PanicInfo<'_>) + Sync + Send + 'static>;
+
+// the number of the guards, the address of the installed hook and the hook it replaced
+struct Installed {
+    guards: usize,
+    hook: usize,
+    prev: Option<Arc<Hook>>,
+}
+
+static INSTALLED: Mutex<Installed> = Mutex::new(Installed {
+    guards: 0,
+    hook: 0,
+    prev: None,
+});
+
+/// install the panic hook that reports the panics inside the generators
+///
+/// it's process wide so it's opt-in. it records the panic site for
+/// `GeneratorPanic::location` and `GeneratorPanic::backtrace` and prints the
+/// panic only with `PanicReport::Site`, the panics out of the generators go
+/// to the hook set before. without it the panics are always printed by the
+/// std hook and the `GeneratorPanic` has no site.
+///
+/// it's installed once for all the guards, the hook set before is restored
+/// when the last guard is dropped, unless it's replaced by `panic::set_hook`
+/// since then or the guard is dropped while panicking
+pub fn install_panic_hook() -> PanicHookGuard {
+    let mut installed = INSTALLED.lock().unwrap();
+    if installed.prev.is_none() {
+        let prev = Arc::new(panic::take_hook());
+        let prev_hook = prev.clone();
+        let hook: Hook = Box::new(move |info| {
+            // the `Done`/`Cancel` are raised by `resume_unwind` that skips the hook
+            let Some(ctx) = current_generator() else {
+                return prev_hook(info);
+            };
+            if let PanicReport::Site = ctx.panic_report {
+                prev_hook(info);
+                // the thread name in the message is not enough to locate the generator
+                if let Some(name) = ctx.name.as_deref() {
+                    eprintln!("note: panicked in generator '{name}' (id {})", ctx.id);
+                }
+            }
+            // the site is lost after the unwinding, keep it for the `GeneratorPanic`
+            ctx.panic_site = Some(PanicSite {
+                location: info.location().map(|l| PanicLocation {
+                    file: l.file().to_owned(),
+                    line: l.line(),
+                    column: l.column(),
+                }),
+                backtrace: Backtrace::capture(),
+            });
+            // the site is cleared at the next yield, see `raw_yield_now`
+            ctx.hooked = true;
+        });
+        installed.hook = hook_addr(&hook);
+        installed.prev = Some(prev);
+        panic::set_hook(hook);
+    }
+    installed.guards += 1;
+    PanicHookGuard { _private: () }
+}
+
+fn hook_addr(hook: &Hook) -> usize {
+    &**hook as *const _ as *const () as usize
+}
+
+/// keeps the panic hook of `install_panic_hook` installed until it's dropped
+#[must_use = "the panic hook is uninstalled when the guard is dropped"]
+#[derive(Debug)]
+pub struct PanicHookGuard {
+    _private: (),
+}
+
+impl Drop for PanicHookGuard {
+    fn drop(&mut self) {
+        let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        installed.guards -= 1;
+        // the hook can't be changed while panicking, it's kept for the next guard
+        if installed.guards > 0 || thread::panicking() {
+            return;
+        }
+        let Some(prev) = installed.prev.take() else {
+            return;
+        };
+        let hook = panic::take_hook();
+        if hook_addr(&hook) != installed.hook {
+            // replaced by someone else, leave it
+            return panic::set_hook(hook);
+        }
+        // release the clone held by the hook
+        drop(hook);
+        match Arc::try_unwrap(prev) {
+            Ok(prev) => panic::set_hook(prev),
+            Err(prev) => panic::set_hook(Box::new(move |info| prev(info))),
+        }
+    }
+}
+
+/// the source location of a panic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicLocation {
+    /// the source file
+    pub file: String,
+    /// the line number
+    pub line: u32,
+    /// the column number
+    pub column: u32,
+}
+
+impl fmt::Display for PanicLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// what the panic hook records at the panic site inside a generator
+pub(crate) struct PanicSite {
+    pub(crate) location: Option<PanicLocation>,
+    pub(crate) backtrace: Backtrace,
+}
+
+/// the panic payload of a generator
+///
+/// the generator catches the panic at its bottom and the resumer gets this
+/// payload by `resume_unwind`, when it passes through the nested generators
+/// each of them is appended to the `chain`. the original payload is kept
+/// in `payload`.
+pub struct GeneratorPanic {
+    message: Option<String>,
+    location: Option<PanicLocation>,
+    backtrace: Backtrace,
+    chain: Vec<(GeneratorId, Option<String>)>,
+    payload: Box<dyn Any + Send>,
+    report: PanicReport,
+}
+
+impl GeneratorPanic {
+    /// wrap the payload caught in the generator, a `GeneratorPanic` of a nested
+    /// generator is passing through and only records this generator
+    pub(crate) fn wrap(ctx: &mut Context, cause: Box<dyn Any + Send>) -> Box<dyn Any + Send> {
+        let link = (GeneratorId(ctx.id), ctx.name.clone());
+        let cause = match cause.downcast::<GeneratorPanic>() {
+            Ok(mut p) => {
+                p.chain.push(link);
+                return p;
+            }
+            Err(cause) => cause,
+        };
+
+        let message = match cause.downcast_ref::<&str>() {
+            Some(s) => Some(s.to_string()),
+            None => cause.downcast_ref::<String>().cloned(),
+        };
+        // the site is missing without the panic hook, see `install_panic_hook`
+        let (location, backtrace) = match ctx.panic_site.take() {
+            Some(site) => (site.location, site.backtrace),
+            None => (None, Backtrace::disabled()),
+        };
+        ctx.update_hooked();
+        let p = GeneratorPanic {
+            message,
+            location,
+            backtrace,
+            chain: vec![link],
+            payload: cause,
+            report: ctx.panic_report.clone(),
+        };
+        if let PanicReport::Callback(f) = &p.report {
+            // a panicking callback must not escape the generator bottom
+            let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| f(&p)));
+        }
+        Box::new(p)
+    }
+
+    /// print the panic if it's propagated to a thread root context with the `Root` policy
+    pub(crate) fn report_root(err: &(dyn Any + Send)) {
+        let Some(p) = err.downcast_ref::<GeneratorPanic>() else {
+            return;
+        };
+        if let PanicReport::Root = p.report {
+            eprintln!("{p}");
+            if p.backtrace.status() == BacktraceStatus::Captured {
+                eprintln!("stack backtrace:\n{}", p.backtrace);
+            }
+        }
+    }
+
+    /// the id of the generator that panicked
+    pub fn id(&self) -> GeneratorId {
+        self.chain[0].0
+    }
+
+    /// the name of the generator that panicked
+    pub fn name(&self) -> Option<&str> {
+        self.chain[0].1.as_deref()
+    }
+
+    /// the panic message if the payload is a string
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// where the panic happened
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.as_ref()
+    }
+
+    /// the backtrace captured at the panic site, see `Backtrace::capture`
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// the generators the panic passed through, from the panicked one to the outermost
+    pub fn chain(&self) -> &[(GeneratorId, Option<String>)] {
+        &self.chain
+    }
+
+    /// the original panic payload
+    pub fn payload(&self) -> &(dyn Any + Send) {
+        &*self.payload
+    }
+
+    /// get the original panic payload
+    pub fn into_payload(self) -> Box<dyn Any + Send> {
+        self.payload
+    }
+}
+
+impl fmt::Display for GeneratorPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.name().unwrap_or("<unnamed>");
+        write!(f, "generator '{name}' (id {}) panicked", self.id())?;
+        if let Some(location) = self.location.as_ref() {
+            write!(f, " at {location}")?;
+        }
+        if let Some(message) = self.message.as_deref() {
+            write!(f, ": {message}")?;
+        }
+        for (id, name) in &self.chain[1..] {
+            let name = name.as_deref().unwrap_or("<unnamed>");
+            write!(f, "\n  in generator '{name}' (id {id})")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for GeneratorPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GeneratorPanic")
+            .field("message", &self.message)
+            .field("location", &self.location)
+            .field("chain", &self.chain)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Error for GeneratorPanic {}
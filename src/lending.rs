@@ -0,0 +1,124 @@
+//! # lending generators
+//!
+//! generators that yield references into their own stack
+//!
+
+use std::marker::PhantomData;
+
+use crate::gen_impl::{LocalGenerator, DEFAULT_STACK_SIZE};
+use crate::scope::Scope;
+
+/// a generator that lends out the data living in its own frame
+///
+/// the reference returned by `next` is valid until the next resume,
+/// so the generator can reuse a buffer without allocating for each item.
+///
+/// ```
+/// use generator::LendingGenerator;
+///
+/// let mut g = LendingGenerator::<str>::new(|mut l| {
+///     let mut buf = String::new();
+///     for word in ["hello", "world"] {
+///         buf.clear();
+///         buf.push_str(word);
+///         l.lend(&mut buf);
+///     }
+/// });
+///
+/// while let Some(s) = g.next() {
+///     s.make_ascii_uppercase();
+///     assert!(s == "HELLO" || s == "WORLD");
+/// }
+/// ```
+pub struct LendingGenerator<'a, T: ?Sized> {
+    gen: LocalGenerator<'a, (), *mut T>,
+}
+
+impl<'a, T: ?Sized + 'a> LendingGenerator<'a, T> {
+    /// create a lending generator with default stack size
+    #[track_caller]
+    pub fn new<F>(f: F) -> Self
+    where
+        for<'s> F: FnOnce(Lender<'s, 'a, T>) + 'a,
+    {
+        Self::new_opt(DEFAULT_STACK_SIZE, f)
+    }
+
+    /// create a lending generator with specified stack size
+    #[track_caller]
+    pub fn new_opt<F>(size: usize, f: F) -> Self
+    where
+        for<'s> F: FnOnce(Lender<'s, 'a, T>) + 'a,
+    {
+        let gen = LocalGenerator::new_scoped_done_unchecked(
+            size,
+            move |scope: Scope<'_, 'a, (), *mut T>| {
+                f(Lender {
+                    scope,
+                    phantom: PhantomData,
+                });
+                None
+            },
+        );
+        LendingGenerator { gen }
+    }
+
+    /// resume the generator and get the lent data
+    ///
+    /// the generator is suspended while the returned reference is alive
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&mut T> {
+        // the data lives in the suspended generator frame
+        self.gen.resume().map(|p| unsafe { &mut *p })
+    }
+
+    /// cancel the generator
+    pub fn cancel(&mut self) {
+        self.gen.cancel()
+    }
+
+    /// is finished
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.gen.is_done()
+    }
+
+    /// get stack total size and used size in word
+    pub fn stack_usage(&self) -> (usize, usize) {
+        self.gen.stack_usage()
+    }
+}
+
+/// the scope passed to a `LendingGenerator` to lend out data
+pub struct Lender<'s, 'a, T: ?Sized> {
+    scope: Scope<'s, 'a, (), *mut T>,
+    // the lent data must not outlive the generator
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<T: ?Sized> Lender<'_, '_, T> {
+    /// lend the data and wait for the next resume
+    /// # Safety
+    /// When yield out, the reference of the captured data must be still valid
+    /// normally, you should always call the `drop` of the generator
+    #[inline]
+    pub unsafe fn lend_unsafe(&mut self, v: &mut T) {
+        // the borrow is kept alive during the suspension
+        self.scope.yield_unsafe(v);
+    }
+
+    /// check if the generator is being closed, see `Scope::is_closing`
+    #[inline]
+    pub fn is_closing(&self) -> bool {
+        self.scope.is_closing()
+    }
+}
+
+impl<T: ?Sized> Lender<'_, 'static, T> {
+    /// lend the data and wait for the next resume
+    // the consumer can only access the data before the next resume
+    #[inline]
+    pub fn lend(&mut self, v: &mut T) {
+        unsafe { self.lend_unsafe(v) }
+    }
+}
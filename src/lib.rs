@@ -9,17 +9,40 @@
 #[macro_use]
 extern crate log;
 
+mod combinator;
 mod detail;
 mod gen_impl;
+mod gen_panic;
+mod lending;
+mod local;
+mod observer;
+mod pipeline;
 mod reg_context;
+pub mod registry;
 mod rt;
+pub mod sched;
 mod scope;
+mod scoped;
 mod stack;
+pub mod stats;
 mod yield_;
 
-pub use crate::gen_impl::{Generator, Gn, LocalGenerator, DEFAULT_STACK_SIZE};
-pub use crate::rt::{get_local_data, is_generator, Error};
+pub use crate::combinator::Peekable;
+pub use crate::gen_impl::{Generator, GeneratorState, Gn, LocalGenerator, DEFAULT_STACK_SIZE};
+pub use crate::gen_panic::{
+    default_panic_report, install_panic_hook, set_default_panic_report, GeneratorPanic,
+    PanicHookGuard, PanicLocation, PanicReport,
+};
+pub use crate::lending::{Lender, LendingGenerator};
+pub use crate::local::{AccessError, LocalKey};
+pub use crate::observer::{default_observer, set_default_observer, GeneratorObserver};
+pub use crate::pipeline::{pipeline, Merge, Pipeline, Sink, SinkScope};
+pub use crate::rt::{
+    current_id, default_cancel_policy, get_local_data, is_generator, set_default_cancel_policy,
+    CancelPolicy, Error, GeneratorId,
+};
 pub use crate::scope::Scope;
+pub use crate::scoped::{scope, GeneratorScope, ScopedGenerator, ScopedYield};
 pub use crate::yield_::{
     co_get_yield, co_set_para, co_yield_with, done, get_yield, yield_, yield_from, yield_with,
 };
@@ -0,0 +1,130 @@
+//! # generator local storage
+//!
+//! typed values stored per generator, like `thread_local!` for threads
+//!
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+
+use crate::rt::current_generator;
+
+/// declare new generator local storage keys of type `LocalKey`
+///
+/// the value is initialized lazily on the first access in each generator
+/// and dropped when the generator is finished
+///
+/// ```
+/// use std::cell::Cell;
+/// use generator::{generator_local, Gn};
+///
+/// generator_local! {
+///     static COUNT: Cell<u32> = Cell::new(0);
+/// }
+///
+/// let g = Gn::<()>::new_scoped(|mut s| {
+///     for _ in 0..3 {
+///         COUNT.with(|c| c.set(c.get() + 1));
+///         s.yield_(COUNT.with(|c| c.get()));
+///     }
+///     0
+/// });
+/// assert_eq!(g.collect::<Vec<_>>(), [1, 2, 3, 0]);
+/// ```
+#[macro_export]
+macro_rules! generator_local {
+    () => {};
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::generator_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::generator_local!($($rest)*);
+    };
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::LocalKey<$t> = {
+            fn __init() -> $t {
+                $init
+            }
+            $crate::LocalKey::new(__init)
+        };
+    };
+}
+
+/// a key of the generator local storage, declared by `generator_local!`
+pub struct LocalKey<T: Send + 'static> {
+    init: fn() -> T,
+}
+
+impl<T: Send + 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        LocalKey { init }
+    }
+
+    /// access the value of the current generator
+    ///
+    /// # Panics
+    ///
+    /// panics if it's not called in a generator context
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.try_with(f)
+            .expect("cannot access a generator local outside of a generator")
+    }
+
+    /// access the value of the current generator
+    /// returns `AccessError` if it's not called in a generator context
+    pub fn try_with<F, R>(&'static self, f: F) -> Result<R, AccessError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let ctx = current_generator().ok_or(AccessError)?;
+
+        let key = self as *const Self as usize;
+        let value = match ctx.locals.iter().find(|slot| slot.key == key) {
+            Some(slot) => slot.value,
+            None => {
+                // the init may access other generator locals
+                let value = Box::new((self.init)());
+                let value = Box::into_raw(value) as *mut (dyn Any + Send);
+                ctx.locals.push(LocalSlot { key, value });
+                value
+            }
+        };
+        // the value is boxed, its address is stable until the generator is finished
+        Ok(f(unsafe { &*(value as *const T) }))
+    }
+}
+
+impl<T: Send + 'static> fmt::Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("LocalKey { .. }")
+    }
+}
+
+/// the error returned by `LocalKey::try_with` outside of a generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError;
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt("not in a generator context", f)
+    }
+}
+
+impl Error for AccessError {}
+
+/// a generator local value owned by the generator context
+pub(crate) struct LocalSlot {
+    key: usize,
+    value: *mut (dyn Any + Send),
+}
+
+impl Drop for LocalSlot {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.value) });
+    }
+}
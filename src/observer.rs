@@ -0,0 +1,111 @@
+//! # generator observer
+//!
+//! instrumentation hooks called on the generator events
+//!
+//! an observer is installed for the new generators by `set_default_observer`
+//! or for one generator by `Generator::set_observer`, the switch path only
+//! checks a null pointer when there is no observer.
+//!
+
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::rt::GeneratorId;
+
+/// the hooks called on the generator events, all of them do nothing by default
+///
+/// the hooks are called on the resumer side of the context switch, the
+/// `elapsed` is the time since the generator is resumed. when a generator is
+/// delegating by `yield_from` both of it and the delegate that's actually
+/// running see the resume and the yield, the same as they are not delegated.
+pub trait GeneratorObserver: Send + Sync {
+    /// the generator is created
+    fn on_create(&self, _id: GeneratorId) {}
+
+    /// the generator is going to run
+    fn on_resume(&self, _id: GeneratorId) {}
+
+    /// the generator yields back
+    fn on_yield(&self, _id: GeneratorId, _elapsed: Duration) {}
+
+    /// the generator returns
+    fn on_complete(&self, _id: GeneratorId, _elapsed: Duration) {}
+
+    /// the generator is cancelled
+    fn on_cancel(&self, _id: GeneratorId) {}
+
+    /// the generator panics, see `GeneratorPanic`
+    fn on_panic(&self, _id: GeneratorId, _elapsed: Duration) {}
+
+    /// the generator is dropped, `lifetime` is the time since it's created
+    fn on_drop(&self, _id: GeneratorId, _lifetime: Duration) {}
+}
+
+// false means not set, there is no default observer
+static OBSERVER_SET: AtomicBool = AtomicBool::new(false);
+static OBSERVER: RwLock<Option<Arc<dyn GeneratorObserver>>> = RwLock::new(None);
+
+/// set the observer for the generators created afterwards
+pub fn set_default_observer(observer: Option<Arc<dyn GeneratorObserver>>) {
+    let mut o = OBSERVER.write().unwrap();
+    OBSERVER_SET.store(observer.is_some(), Ordering::Release);
+    *o = observer;
+}
+
+/// get the observer for new generators
+pub fn default_observer() -> Option<Arc<dyn GeneratorObserver>> {
+    if !OBSERVER_SET.load(Ordering::Acquire) {
+        return None;
+    }
+    OBSERVER.read().unwrap().clone()
+}
+
+/// the observer attached to a generator context
+pub(crate) struct Observed {
+    pub(crate) observer: Arc<dyn GeneratorObserver>,
+    id: GeneratorId,
+    created: Instant,
+    resumed: Instant,
+}
+
+impl Observed {
+    pub(crate) fn new(observer: Arc<dyn GeneratorObserver>, id: GeneratorId) -> Box<Self> {
+        let now = Instant::now();
+        Box::new(Observed {
+            observer,
+            id,
+            created: now,
+            resumed: now,
+        })
+    }
+
+    /// remove the observer without the `on_drop`
+    pub(crate) fn detach(self) {
+        let this = ManuallyDrop::new(self);
+        drop(unsafe { ptr::read(&this.observer) });
+    }
+
+    #[inline]
+    pub(crate) fn id(&self) -> GeneratorId {
+        self.id
+    }
+
+    pub(crate) fn resume(&mut self) {
+        self.observer.on_resume(self.id);
+        self.resumed = Instant::now();
+    }
+
+    /// the time since the last resume
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.resumed.elapsed()
+    }
+}
+
+impl Drop for Observed {
+    fn drop(&mut self) {
+        self.observer.on_drop(self.id, self.created.elapsed());
+    }
+}
@@ -0,0 +1,327 @@
+//! # push based pipeline
+//!
+//! sink generators that consume the pushed values and the builder
+//! to connect them into a processing pipeline
+//!
+//! pushing a value runs all the stages synchronously, it returns only
+//! after the downstream stages have consumed the value, so there is
+//! no buffering between the stages.
+//!
+
+use std::sync::{Arc, Mutex};
+
+use crate::gen_impl::{Generator, GeneratorObj, DEFAULT_STACK_SIZE};
+use crate::scope::Scope;
+
+/// a generator that consumes the pushed values and produces
+/// the result when it's closed
+///
+/// ```
+/// use generator::Sink;
+///
+/// let mut sum = Sink::new(|mut rx| {
+///     let mut sum = 0;
+///     while let Some(v) = rx.recv() {
+///         sum += v;
+///     }
+///     sum
+/// });
+///
+/// for i in 0..10 {
+///     sum.push(i).unwrap();
+/// }
+/// assert_eq!(sum.close(), Some(45));
+/// ```
+pub struct Sink<A: 'static, R: 'static = ()> {
+    gen: Generator<'static, A, Option<R>>,
+    ret: Option<R>,
+}
+
+impl<A: Send + 'static, R: Send + 'static> Sink<A, R> {
+    /// create a sink, the closure receives the values until the sink is closed
+    #[track_caller]
+    pub fn new<F>(f: F) -> Self
+    where
+        F: FnOnce(SinkScope<A, R>) -> R + Send + 'static,
+    {
+        Self::new_done(move |rx| Some(f(rx)))
+    }
+
+    // the stage sinks return `None` when the downstream has no result
+    #[track_caller]
+    fn new_done<F>(f: F) -> Self
+    where
+        F: FnOnce(SinkScope<A, R>) -> Option<R> + Send + 'static,
+    {
+        let gen = GeneratorObj::new_scoped_done_unchecked(
+            DEFAULT_STACK_SIZE,
+            move |scope: Scope<'_, 'static, A, Option<R>>| {
+                Some(f(SinkScope {
+                    scope,
+                    started: false,
+                }))
+            },
+        );
+        Sink { gen, ret: None }
+    }
+
+    /// push a value into the sink, it returns after the value is consumed
+    ///
+    /// the value is given back if the sink is already finished
+    pub fn push(&mut self, v: A) -> Result<(), A> {
+        if self.gen.is_done() {
+            return Err(v);
+        }
+        // the sink may finish without waiting for the close
+        if let Some(Some(r)) = self.gen.raw_send(Some(v)) {
+            self.ret = Some(r);
+        }
+        Ok(())
+    }
+
+    /// close the sink and get the result
+    ///
+    /// the `recv` in the sink returns `None` to flush and finish, if it
+    /// still waits for more values, the sink is closed like `Generator::close`
+    pub fn close(&mut self) -> Option<R> {
+        if !self.gen.is_done() {
+            if let Some(Some(r)) = self.gen.raw_send(None) {
+                self.ret = Some(r);
+            }
+        }
+        if !self.gen.is_done() {
+            if let Some(Some(r)) = self.gen.close() {
+                self.ret = Some(r);
+            }
+        }
+        self.ret.take()
+    }
+
+    /// is finished
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.gen.is_done()
+    }
+
+    /// create a sink that converts the values by `f` before pushing them into this one
+    pub fn map_in<B, F>(self, mut f: F) -> Sink<B, R>
+    where
+        B: Send + 'static,
+        F: FnMut(B) -> A + Send + 'static,
+    {
+        self.stage(move |rx, tx| {
+            while let Some(v) = rx.recv() {
+                // finish as soon as the downstream is finished
+                if tx.push(f(v)).is_err() || tx.is_done() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// create a sink that only pushes the matched values into this one
+    pub fn filter_in<P>(self, mut pred: P) -> Sink<A, R>
+    where
+        P: FnMut(&A) -> bool + Send + 'static,
+    {
+        self.stage(move |rx, tx| {
+            while let Some(v) = rx.recv() {
+                if pred(&v) && (tx.push(v).is_err() || tx.is_done()) {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// create a sink that runs the stage function in front of this one
+    ///
+    /// the stage receives the values from `rx` and pushes into `tx`,
+    /// this sink is closed when the stage function returns
+    pub fn stage<B, F>(self, f: F) -> Sink<B, R>
+    where
+        B: Send + 'static,
+        F: FnOnce(&mut SinkScope<B, R>, &mut Sink<A, R>) + Send + 'static,
+    {
+        let mut tx = self;
+        Sink::new_done(move |mut rx| {
+            f(&mut rx, &mut tx);
+            tx.close()
+        })
+    }
+
+    /// share the sink with several upstream stages
+    pub fn merge(self) -> Merge<A, R> {
+        Merge {
+            sink: Arc::new(Mutex::new(self)),
+        }
+    }
+}
+
+impl<A: Clone + Send + 'static, R: Send + 'static> Sink<A, Vec<Option<R>>> {
+    /// create a sink that broadcasts the values to all the sinks
+    ///
+    /// it's finished when all the sinks are finished, the results are
+    /// in the same order as the sinks
+    pub fn tee(sinks: Vec<Sink<A, R>>) -> Self {
+        let mut sinks = sinks;
+        Sink::new(move |mut rx: SinkScope<A, _>| {
+            while let Some(v) = rx.recv() {
+                let mut done = true;
+                for sink in sinks.iter_mut().filter(|s| !s.is_done()) {
+                    let _ = sink.push(v.clone());
+                    done &= sink.is_done();
+                }
+                if done {
+                    break;
+                }
+            }
+            sinks.iter_mut().map(|s| s.close()).collect()
+        })
+    }
+}
+
+/// the scope passed to a sink to receive the pushed values
+pub struct SinkScope<'s, A: 'static, R: 'static> {
+    scope: Scope<'s, 'static, A, Option<R>>,
+    started: bool,
+}
+
+impl<A: 'static, R: 'static> SinkScope<'_, A, R> {
+    /// wait for the next pushed value, `None` means the sink is closed
+    pub fn recv(&mut self) -> Option<A> {
+        if self.started {
+            return self.scope.yield_(None);
+        }
+        // the first value is passed in when the sink is started
+        self.started = true;
+        self.scope.get_yield()
+    }
+}
+
+/// a sink shared by several upstream stages, created by `Sink::merge`
+///
+/// the shared sink must not be pushed from its own downstream stages
+pub struct Merge<A: 'static, R: 'static> {
+    sink: Arc<Mutex<Sink<A, R>>>,
+}
+
+impl<A, R> Clone for Merge<A, R> {
+    fn clone(&self) -> Self {
+        Merge {
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<A: Send + 'static, R: Send + 'static> Merge<A, R> {
+    /// push a value into the shared sink
+    pub fn push(&self, v: A) -> Result<(), A> {
+        self.sink.lock().unwrap().push(v)
+    }
+
+    /// is the shared sink finished
+    pub fn is_done(&self) -> bool {
+        self.sink.lock().unwrap().is_done()
+    }
+
+    /// create an input sink that forwards the values into the shared sink
+    ///
+    /// closing the input doesn't close the shared sink
+    pub fn input(&self) -> Sink<A> {
+        let merge = self.clone();
+        Sink::new(move |mut rx| {
+            while let Some(v) = rx.recv() {
+                if merge.push(v).is_err() || merge.is_done() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// close the shared sink and get the result
+    pub fn close(&self) -> Option<R> {
+        self.sink.lock().unwrap().close()
+    }
+}
+
+/// create a pipeline builder that receives `A`
+pub fn pipeline<A: 'static, R: 'static>() -> Pipeline<A, A, R> {
+    Pipeline {
+        build: Box::new(|sink| sink),
+    }
+}
+
+/// the builder that connects the stages from the upstream to the downstream
+///
+/// ```
+/// let mut sink = generator::pipeline::<u32, _>()
+///     .map(|v| v * v)
+///     .filter(|v| v % 2 == 0)
+///     .into_sink(generator::Sink::new(|mut rx| {
+///         let mut v = Vec::new();
+///         while let Some(i) = rx.recv() {
+///             v.push(i);
+///         }
+///         v
+///     }));
+///
+/// for i in 0..6 {
+///     sink.push(i).unwrap();
+/// }
+/// assert_eq!(sink.close(), Some(vec![0, 4, 16]));
+/// ```
+pub struct Pipeline<A: 'static, B: 'static, R: 'static> {
+    build: Build<A, B, R>,
+}
+
+// connect the downstream sink and return the upstream one
+type Build<A, B, R> = Box<dyn FnOnce(Sink<B, R>) -> Sink<A, R>>;
+
+impl<A: 'static, B: Send + 'static, R: Send + 'static> Pipeline<A, B, R> {
+    /// add a stage that converts the values
+    pub fn map<C, F>(self, f: F) -> Pipeline<A, C, R>
+    where
+        C: Send + 'static,
+        F: FnMut(B) -> C + Send + 'static,
+    {
+        let build = self.build;
+        Pipeline {
+            build: Box::new(move |sink| build(sink.map_in(f))),
+        }
+    }
+
+    /// add a stage that only passes the matched values
+    pub fn filter<P>(self, pred: P) -> Pipeline<A, B, R>
+    where
+        P: FnMut(&B) -> bool + Send + 'static,
+    {
+        let build = self.build;
+        Pipeline {
+            build: Box::new(move |sink| build(sink.filter_in(pred))),
+        }
+    }
+
+    /// add a stage function, see `Sink::stage`
+    pub fn stage<C, F>(self, f: F) -> Pipeline<A, C, R>
+    where
+        C: Send + 'static,
+        F: FnOnce(&mut SinkScope<B, R>, &mut Sink<C, R>) + Send + 'static,
+    {
+        let build = self.build;
+        Pipeline {
+            build: Box::new(move |sink| build(sink.stage(f))),
+        }
+    }
+
+    /// connect the pipeline to the sink
+    pub fn into_sink(self, sink: Sink<B, R>) -> Sink<A, R> {
+        (self.build)(sink)
+    }
+}
+
+impl<A: 'static, B: Clone + Send + 'static, R: Send + 'static> Pipeline<A, B, Vec<Option<R>>> {
+    /// connect the pipeline to all the sinks, see `Sink::tee`
+    pub fn broadcast(self, sinks: Vec<Sink<B, R>>) -> Sink<A, Vec<Option<R>>> {
+        self.into_sink(Sink::tee(sinks))
+    }
+}
@@ -19,6 +19,12 @@ impl RegContext {
         self.regs.prefetch();
     }
 
+    /// the stack pointer where the context is suspended
+    #[inline]
+    pub fn sp(&self) -> usize {
+        self.regs.sp()
+    }
+
     /// Create a new context, only used in tests
     #[cfg(test)]
     fn new(init: InitFn, arg: usize, start: *mut usize, stack: &Stack) -> RegContext {
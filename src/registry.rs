@@ -0,0 +1,543 @@
+//! # generator registry
+//!
+//! an opt-in process wide registry of the live generators
+//!
+//! after `enable` is called, every new generator registers itself and
+//! deregisters when it's dropped, `snapshot` lists them so that a hanging
+//! service can tell which generators exist and where they are stuck.
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, Instant};
+
+use crate::gen_impl::GeneratorState;
+use crate::rt::GeneratorId;
+use crate::stats::{GeneratorStats, StatsCell};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+fn entries() -> &'static Mutex<HashMap<u64, Arc<Entry>>> {
+    static ENTRIES: OnceLock<Mutex<HashMap<u64, Arc<Entry>>>> = OnceLock::new();
+    ENTRIES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// start registering the new generators
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// stop registering the new generators, the registered ones are kept until dropped
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// is the registry enabled
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// the registry record of a generator, updated by the generator itself
+pub(crate) struct Entry {
+    id: u64,
+    location: &'static Location<'static>,
+    name: Mutex<Option<String>>,
+    state: AtomicU8,
+    // the token of the thread, it's compared without locking the thread
+    thread_token: AtomicU64,
+    thread: Mutex<Thread>,
+    stack_size: usize,
+    stack_used: AtomicUsize,
+    yielded_at: AtomicPtr<Location<'static>>,
+    stats: Mutex<Option<Arc<StatsCell>>>,
+}
+
+impl Entry {
+    /// set the name of the generator
+    pub(crate) fn set_name(&self, name: Option<String>) {
+        *self.name.lock().unwrap() = name;
+    }
+
+    /// the generator is going to run on the current thread
+    pub(crate) fn on_resume(&self) {
+        self.state
+            .store(state_to_u8(GeneratorState::Running), Ordering::Relaxed);
+        let token = thread_token();
+        if self.thread_token.load(Ordering::Relaxed) != token {
+            self.thread_token.store(token, Ordering::Relaxed);
+            *self.thread.lock().unwrap() = thread::current();
+        }
+    }
+
+    /// the generator is switched out
+    pub(crate) fn set_state(&self, state: GeneratorState) {
+        self.state.store(state_to_u8(state), Ordering::Relaxed);
+    }
+
+    /// record the yield site and the stack usage there, the size is in words
+    pub(crate) fn on_yield(&self, location: &'static Location<'static>, used: usize) {
+        let location = location as *const Location<'static> as *mut _;
+        self.yielded_at.store(location, Ordering::Relaxed);
+        self.stack_used.store(used, Ordering::Relaxed);
+    }
+
+    /// the stats recording is enabled for the generator
+    pub(crate) fn set_stats(&self, stats: Arc<StatsCell>) {
+        *self.stats.lock().unwrap() = Some(stats);
+    }
+
+    fn info(&self) -> GeneratorInfo {
+        let thread = self.thread.lock().unwrap();
+        GeneratorInfo {
+            id: GeneratorId(self.id),
+            name: self.name.lock().unwrap().clone(),
+            state: state_from_u8(self.state.load(Ordering::Relaxed)),
+            location: self.location,
+            thread: thread_name(&thread),
+            stack_size: self.stack_size,
+            stack_used: self.stack_used.load(Ordering::Relaxed),
+            yielded_at: unsafe { self.yielded_at.load(Ordering::Relaxed).as_ref() },
+            stats: self.stats.lock().unwrap().as_ref().map(|s| s.get()),
+        }
+    }
+}
+
+// a cheap per thread token, `thread::current` clones an `Arc` each time
+fn thread_token() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static TOKEN: u64 = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    TOKEN.with(|t| *t)
+}
+
+fn thread_name(thread: &Thread) -> String {
+    match thread.name() {
+        Some(name) => name.to_owned(),
+        None => format!("{:?}", thread.id()),
+    }
+}
+
+fn state_to_u8(state: GeneratorState) -> u8 {
+    state as u8
+}
+
+fn state_from_u8(v: u8) -> GeneratorState {
+    match v {
+        0 => GeneratorState::Created,
+        1 => GeneratorState::Suspended,
+        2 => GeneratorState::Running,
+        3 => GeneratorState::Completed,
+        4 => GeneratorState::Cancelled,
+        _ => GeneratorState::Panicked,
+    }
+}
+
+/// register a new generator if the registry is enabled
+pub(crate) fn register(
+    id: u64,
+    location: &'static Location<'static>,
+    stack_size: usize,
+    stats: Option<Arc<StatsCell>>,
+) -> Option<Arc<Entry>> {
+    if !is_enabled() {
+        return None;
+    }
+
+    let entry = Arc::new(Entry {
+        id,
+        location,
+        name: Mutex::new(None),
+        state: AtomicU8::new(state_to_u8(GeneratorState::Created)),
+        thread_token: AtomicU64::new(thread_token()),
+        thread: Mutex::new(thread::current()),
+        stack_size,
+        stack_used: AtomicUsize::new(0),
+        yielded_at: AtomicPtr::new(std::ptr::null_mut()),
+        stats: Mutex::new(stats),
+    });
+    entries().lock().unwrap().insert(id, entry.clone());
+    Some(entry)
+}
+
+/// remove the dropped generator
+pub(crate) fn deregister(entry: &Entry) {
+    entries().lock().unwrap().remove(&entry.id);
+}
+
+/// the information of a registered generator
+#[derive(Debug, Clone)]
+pub struct GeneratorInfo {
+    /// the generator id
+    pub id: GeneratorId,
+    /// the generator name
+    pub name: Option<String>,
+    /// the state when the snapshot is taken
+    pub state: GeneratorState,
+    /// where the generator is created
+    pub location: &'static Location<'static>,
+    /// the thread that created or last resumed the generator
+    pub thread: String,
+    /// the stack size in words
+    pub stack_size: usize,
+    /// the stack used at the last suspension in words
+    pub stack_used: usize,
+    /// where the generator yielded the last time
+    pub yielded_at: Option<&'static Location<'static>>,
+    /// the stats if they are recorded, see `stats`
+    pub stats: Option<GeneratorStats>,
+}
+
+impl fmt::Display for GeneratorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "generator {} '{}' {:?} created at {} on thread '{}', stack {}/{} words",
+            self.id,
+            self.name.as_deref().unwrap_or("<unnamed>"),
+            self.state,
+            self.location,
+            self.thread,
+            self.stack_used,
+            self.stack_size
+        )?;
+        if let Some(location) = self.yielded_at {
+            write!(f, ", last yield at {location}")?;
+        }
+        if let Some(stats) = self.stats {
+            write!(
+                f,
+                ", {} resumes, {} yields, cpu {:?}",
+                stats.resumes, stats.yields, stats.cpu_time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn live_entries() -> Vec<Arc<Entry>> {
+    entries().lock().unwrap().values().cloned().collect()
+}
+
+/// get the information of all the registered generators, ordered by id
+pub fn snapshot() -> Vec<GeneratorInfo> {
+    let entries = live_entries();
+    let mut infos: Vec<_> = entries.iter().map(|e| e.info()).collect();
+    infos.sort_by_key(|info| info.id);
+    infos
+}
+
+/// write the snapshot, one generator per line
+pub fn dump<W: Write>(mut w: W) -> io::Result<()> {
+    let infos = snapshot();
+    writeln!(w, "{} live generators", infos.len())?;
+    for info in infos {
+        writeln!(w, "{info}")?;
+    }
+    Ok(())
+}
+
+// the number of the running watchdogs and the enabled state before the first one
+static WATCHDOGS: Mutex<(usize, bool)> = Mutex::new((0, false));
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+/// is any watchdog running, the switches are published to it
+#[inline]
+pub(crate) fn is_watched() -> bool {
+    WATCHING.load(Ordering::Relaxed)
+}
+
+/// the generator running on a thread, sampled by the watchdog
+struct Running {
+    entry: Mutex<Option<Arc<Entry>>>,
+    switches: AtomicU64,
+}
+
+fn threads() -> &'static Mutex<Vec<Weak<Running>>> {
+    static THREADS: Mutex<Vec<Weak<Running>>> = Mutex::new(Vec::new());
+    &THREADS
+}
+
+thread_local! {
+    static RUNNING: Arc<Running> = {
+        let running = Arc::new(Running {
+            entry: Mutex::new(None),
+            switches: AtomicU64::new(0),
+        });
+        threads().lock().unwrap().push(Arc::downgrade(&running));
+        running
+    };
+}
+
+/// the current thread switches to the generator of the entry, `None` if it's
+/// not registered or it's the thread root context
+// the resumer may be a generator moved to another thread, don't cache the TLS
+#[inline(never)]
+pub(crate) fn on_switch(entry: Option<&Arc<Entry>>) {
+    let _ = RUNNING.try_with(|running| {
+        *running.entry.lock().unwrap() = entry.cloned();
+        running.switches.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+fn live_threads() -> Vec<Arc<Running>> {
+    let mut threads = threads().lock().unwrap();
+    threads.retain(|t| t.strong_count() > 0);
+    threads.iter().filter_map(Weak::upgrade).collect()
+}
+
+/// a background thread that reports the generators running too long without yielding
+///
+/// it samples the generator running on top of each thread, the one staying
+/// there with no switch for longer than the threshold is reported once until
+/// the thread switches again. the registry is enabled when the watchdog is
+/// started and the previous state is restored when the last watchdog is
+/// dropped. only the registered generators are watched, the ones created
+/// before the registry is enabled are not seen.
+///
+/// the stuck generator is busy on its own thread, so its backtrace can't be
+/// captured here, the report has the creation site, the last yield site and
+/// the thread instead
+pub struct Watchdog {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// start the watchdog, `on_stuck` is called with the generator and how long it's running
+    pub fn start<F>(threshold: Duration, on_stuck: F) -> io::Result<Watchdog>
+    where
+        F: Fn(&GeneratorInfo, Duration) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let interval = (threshold / 4).max(Duration::from_millis(1));
+        let handle = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("generator-watchdog".into())
+                .spawn(move || {
+                    // the thread => (switches, the first time seen with the switches, reported)
+                    let mut samples = HashMap::<usize, (u64, Instant, bool)>::new();
+                    while !stop.load(Ordering::Relaxed) {
+                        thread::park_timeout(interval);
+                        let now = Instant::now();
+                        let mut seen = HashMap::with_capacity(samples.len());
+                        for running in live_threads() {
+                            let switches = running.switches.load(Ordering::Relaxed);
+                            let Some(entry) = running.entry.lock().unwrap().clone() else {
+                                continue;
+                            };
+                            let key = Arc::as_ptr(&running) as usize;
+                            let mut sample = match samples.remove(&key) {
+                                Some(s) if s.0 == switches => s,
+                                _ => (switches, now, false),
+                            };
+                            let elapsed = now - sample.1;
+                            if !sample.2 && elapsed >= threshold {
+                                sample.2 = true;
+                                on_stuck(&entry.info(), elapsed);
+                            }
+                            seen.insert(key, sample);
+                        }
+                        samples = seen;
+                    }
+                })?
+        };
+
+        let mut watchdogs = WATCHDOGS.lock().unwrap();
+        if watchdogs.0 == 0 {
+            watchdogs.1 = is_enabled();
+            enable();
+            WATCHING.store(true, Ordering::Relaxed);
+        }
+        watchdogs.0 += 1;
+        Ok(Watchdog {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// start the watchdog that prints the stuck generators to stderr
+    pub fn stderr(threshold: Duration) -> io::Result<Watchdog> {
+        Watchdog::start(threshold, |info, elapsed| {
+            eprintln!("{info} is running for {elapsed:?} without yielding");
+        })
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let mut watchdogs = WATCHDOGS.lock().unwrap();
+        watchdogs.0 -= 1;
+        if watchdogs.0 == 0 {
+            WATCHING.store(false, Ordering::Relaxed);
+            if !watchdogs.1 {
+                disable();
+            }
+        }
+        drop(watchdogs);
+
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Watchdog { .. }")
+    }
+}
+
+#[cfg(unix)]
+pub use self::signal::dump_on_signal;
+
+#[cfg(unix)]
+mod signal {
+    use std::io;
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    static PIPE_WR: AtomicI32 = AtomicI32::new(-1);
+
+    // the actions replaced by `dump_on_signal`, indexed by the signal number
+    const MAX_SIGNAL: usize = 65;
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NONE: AtomicUsize = AtomicUsize::new(0);
+    static OLD_HANDLER: [AtomicUsize; MAX_SIGNAL] = [NONE; MAX_SIGNAL];
+    static OLD_FLAGS: [AtomicUsize; MAX_SIGNAL] = [NONE; MAX_SIGNAL];
+
+    extern "C" fn handler(signum: libc::c_int, info: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+        // only async signal safe calls here, the dump is done by the thread
+        let fd = PIPE_WR.load(Ordering::Relaxed);
+        if fd >= 0 {
+            let b = 0u8;
+            unsafe { libc::write(fd, &b as *const u8 as *const libc::c_void, 1) };
+        }
+
+        // chain to the handler installed before, the default action and ignore are skipped
+        let old = OLD_HANDLER[signum as usize].load(Ordering::Relaxed);
+        if old == libc::SIG_DFL || old == libc::SIG_IGN {
+            return;
+        }
+        let flags = OLD_FLAGS[signum as usize].load(Ordering::Relaxed) as libc::c_int;
+        unsafe {
+            if flags & libc::SA_SIGINFO != 0 {
+                let f: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                    std::mem::transmute(old);
+                f(signum, info, ctx);
+            } else {
+                let f: extern "C" fn(libc::c_int) = std::mem::transmute(old);
+                f(signum);
+            }
+        }
+    }
+
+    /// dump the registry to stderr when the signal is received, e.g. `libc::SIGUSR1`
+    ///
+    /// a background thread is spawned to write the dump,
+    /// it also enables the registry. the handler installed before is still
+    /// called after the dump is requested
+    pub fn dump_on_signal(signum: i32) -> io::Result<()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let _guard = LOCK.lock().unwrap();
+
+        if signum <= 0 || signum as usize >= MAX_SIGNAL {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        if PIPE_WR.load(Ordering::Relaxed) < 0 {
+            let mut fds = [0; 2];
+            if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let rd = fds[0];
+            std::thread::Builder::new()
+                .name("generator-registry-dump".into())
+                .spawn(move || loop {
+                    let mut b = 0u8;
+                    let n = unsafe { libc::read(rd, &mut b as *mut u8 as *mut libc::c_void, 1) };
+                    if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    if n <= 0 {
+                        break;
+                    }
+                    let _ = super::dump(io::stderr().lock());
+                })?;
+            PIPE_WR.store(fds[1], Ordering::Relaxed);
+        }
+
+        unsafe {
+            let handler = handler
+                as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+                as libc::sighandler_t;
+            let mut old: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(signum, std::ptr::null(), &mut old) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // it's already installed, don't chain to itself
+            if old.sa_sigaction != handler {
+                OLD_HANDLER[signum as usize].store(old.sa_sigaction, Ordering::Relaxed);
+                OLD_FLAGS[signum as usize].store(old.sa_flags as usize, Ordering::Relaxed);
+            }
+
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler;
+            action.sa_flags = libc::SA_RESTART | libc::SA_SIGINFO;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signum, &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        super::enable();
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dump_on_signal() {
+        dump_on_signal(libc::SIGUSR1).unwrap();
+        assert!(is_enabled());
+        // the dump is written by the background thread
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR1) }, 0);
+    }
+
+    #[test]
+    fn test_dump_on_signal_chain() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static CALLED: AtomicBool = AtomicBool::new(false);
+        extern "C" fn old_handler(_: libc::c_int) {
+            CALLED.store(true, Ordering::Relaxed);
+        }
+
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = old_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            assert_eq!(
+                libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut()),
+                0
+            );
+        }
+        dump_on_signal(libc::SIGUSR2).unwrap();
+        // installing it again doesn't lose the old handler
+        dump_on_signal(libc::SIGUSR2).unwrap();
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR2) }, 0);
+        assert!(CALLED.load(Ordering::Relaxed));
+    }
+}
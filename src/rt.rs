@@ -8,8 +8,16 @@ use std::mem::MaybeUninit;
 use std::ptr;
 #[cfg(all(not(debug_assertions), any(windows, target_os = "macos")))]
 use std::sync::atomic::{compiler_fence, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicU8};
+use std::sync::Arc;
 
+use crate::gen_panic::{PanicReport, PanicSite};
+use crate::local::LocalSlot;
+use crate::observer::Observed;
 use crate::reg_context::RegContext;
+use crate::registry::Entry;
+use crate::stack::{Func, Stack, StackBox};
+use crate::stats::StatsCell;
 
 thread_local! {
     // each thread has it's own generator context stack
@@ -31,6 +39,99 @@ pub enum Error {
     ContextErr,
 }
 
+/// a deferred cleanup function registered by `Scope::defer`
+pub enum Defer {
+    /// allocated in the defer area of the generator stack
+    Stack(Func),
+    /// the defer area is full
+    Heap(Box<dyn FnOnce()>),
+}
+
+/// the way to cancel an unfinished generator
+///
+/// `Unwind` resumes the generator with a `Cancel` panic, which needs unwinding.
+/// the other policies never unwind, the generator is only switched in to do
+/// the cleanup at the yield point and its suspended frames are discarded, so
+/// they also work with `panic = "abort"` or when foreign frames that can't be
+/// unwound sit between the yield and the generator entry. the discarded frames
+/// are never dropped, so they are only used when they are opted in, see
+/// `Generator::set_cancel_policy`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CancelPolicy {
+    /// unwind the generator stack with a `Cancel` panic
+    Unwind,
+    /// discard the suspended frames without running any drop
+    ///
+    /// the generators delegated by `yield_from` are discarded the same way,
+    /// they are never dropped and their stacks are leaked
+    Leak,
+    /// run the `Scope::defer` cleanup and then discard the suspended frames,
+    /// the delegates are handled as `Leak` does
+    Defer,
+}
+
+impl Default for CancelPolicy {
+    /// always `Unwind`, with `panic = "abort"` the cancel of a suspended
+    /// generator aborts unless the other policies are opted in
+    fn default() -> Self {
+        CancelPolicy::Unwind
+    }
+}
+
+// 0 means not set, use the `CancelPolicy::default()`
+static CANCEL_POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// set the cancel policy for the generators created afterwards
+///
+/// # Safety
+///
+/// with `Leak` or `Defer` the suspended frames of the cancelled generators
+/// are discarded without drop, all the generators created afterwards must
+/// not rely on the drop of their locals across a yield for soundness, e.g.
+/// a guard borrowed by a `scoped` thread or a pinned value
+pub unsafe fn set_default_cancel_policy(policy: CancelPolicy) {
+    let v = match policy {
+        CancelPolicy::Unwind => 1,
+        CancelPolicy::Leak => 2,
+        CancelPolicy::Defer => 3,
+    };
+    CANCEL_POLICY.store(v, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// get the cancel policy for new generators
+pub fn default_cancel_policy() -> CancelPolicy {
+    match CANCEL_POLICY.load(std::sync::atomic::Ordering::Relaxed) {
+        1 => CancelPolicy::Unwind,
+        2 => CancelPolicy::Leak,
+        3 => CancelPolicy::Defer,
+        _ => CancelPolicy::default(),
+    }
+}
+
+/// the unique id of a generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GeneratorId(pub(crate) u64);
+
+impl GeneratorId {
+    /// alloc a new id
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        GeneratorId(NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+    }
+
+    /// get the id as a number
+    #[inline]
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for GeneratorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 /// generator context
 #[repr(C)]
 #[repr(align(128))]
@@ -53,6 +154,38 @@ pub struct Context {
     pub err: Option<Box<dyn Any + Send>>,
     /// cached stack guard for fast path
     pub stack_guard: (usize, usize),
+    /// the generator is resumed by `close` to do the cleanup
+    pub closing: bool,
+    /// any of the registry entry, panic site, observer and stats is set,
+    /// the switches only go through their hooks when it's true
+    pub(crate) hooked: bool,
+    /// the innermost generator delegated by `yield_from`, resumed directly
+    pub delegate: *mut Context,
+    /// the generator that delegates to this one by `yield_from`
+    pub delegator: *mut Context,
+    /// the resumer registers linked at the stack bottom, see `set_resumer`
+    pub(crate) resumer: usize,
+    /// deferred cleanup functions, run in LIFO order when the generator is finished
+    pub defers: Vec<Defer>,
+    /// the area on the generator stack where the deferred functions are allocated
+    pub(crate) defer_area: Option<Stack>,
+    /// generator local values, dropped when the generator is finished
+    pub locals: Vec<LocalSlot>,
+    /// the generator id, 0 for the thread root context
+    pub id: u64,
+    /// the generator name
+    pub name: Option<String>,
+    /// the record in the registry if it's enabled
+    pub(crate) entry: Option<Arc<Entry>>,
+    /// recorded by the panic hook, taken when the panic is caught at the bottom,
+    /// cleared at the yield if the panic is caught by the generator itself
+    pub(crate) panic_site: Option<PanicSite>,
+    /// how the panics inside the generator are reported
+    pub(crate) panic_report: PanicReport,
+    /// the observer of the generator events
+    pub(crate) observer: Option<Box<Observed>>,
+    /// the switch counters and cpu time if they are recorded
+    pub(crate) stats: Option<Arc<StatsCell>>,
 }
 
 impl Context {
@@ -68,7 +201,44 @@ impl Context {
             parent: ptr::null_mut(),
             local_data: ptr::null_mut(),
             stack_guard: (0, 0),
+            closing: false,
+            delegate: ptr::null_mut(),
+            delegator: ptr::null_mut(),
+            defers: Vec::new(),
+            defer_area: None,
+            locals: Vec::new(),
+            id: 0,
+            name: None,
+            entry: None,
+            panic_site: None,
+            panic_report: PanicReport::Site,
+            observer: None,
+            stats: None,
+            hooked: false,
+            resumer: 0,
+        }
+    }
+
+    /// update the `hooked` flag after any of the hooked fields is changed
+    #[inline]
+    pub(crate) fn update_hooked(&mut self) {
+        self.hooked = self.entry.is_some()
+            || self.panic_site.is_some()
+            || self.observer.is_some()
+            || self.stats.is_some();
+    }
+
+    /// is the generator or any of its `yield_from` delegators being closed
+    pub(crate) fn is_closing(&self) -> bool {
+        let mut ctx: *const Context = self;
+        while !ctx.is_null() {
+            let c = unsafe { &*ctx };
+            if c.closing {
+                return true;
+            }
+            ctx = c.delegator;
         }
+        false
     }
 
     /// judge it's generator context
@@ -77,6 +247,49 @@ impl Context {
         !std::ptr::eq(self.parent, self)
     }
 
+    /// register a deferred cleanup function, it's allocated on the generator
+    /// stack if there is room in the defer area
+    ///
+    /// # Safety
+    ///
+    /// the defers must be consumed before anything `f` captures is gone
+    pub unsafe fn push_defer<F: FnOnce()>(&mut self, f: F) {
+        let defer = match self.defer_area.as_mut() {
+            Some(area) if area.can_alloc::<F>() => Defer::Stack(StackBox::new_fn_once(area, f)),
+            _ => {
+                let f: Box<dyn FnOnce() + '_> = Box::new(f);
+                Defer::Heap(std::mem::transmute::<
+                    Box<dyn FnOnce() + '_>,
+                    Box<dyn FnOnce()>,
+                >(f))
+            }
+        };
+        self.defers.push(defer);
+    }
+
+    /// run the deferred cleanup functions in LIFO order
+    pub fn run_defers(&mut self) {
+        while let Some(f) = self.defers.pop() {
+            match f {
+                Defer::Stack(f) => f.call_once(),
+                Defer::Heap(f) => f(),
+            }
+        }
+    }
+
+    /// the name used in the messages
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("<unnamed>")
+    }
+
+    /// drop the generator local values
+    pub fn drop_locals(&mut self) {
+        // the drop may access the generator locals again
+        while !self.locals.is_empty() {
+            drop(std::mem::take(&mut self.locals));
+        }
+    }
+
     /// get current generator send para
     #[inline]
     pub fn get_para<A>(&mut self) -> Option<A>
@@ -323,6 +536,36 @@ pub fn is_generator() -> bool {
     !root.child.is_null()
 }
 
+/// get the context of the current running generator
+#[cfg_attr(
+    all(not(debug_assertions), any(windows, target_os = "macos")),
+    inline(never)
+)]
+#[cfg_attr(
+    not(all(not(debug_assertions), any(windows, target_os = "macos"))),
+    inline
+)]
+pub(crate) fn current_generator() -> Option<&'static mut Context> {
+    let env = ContextStack::current();
+
+    #[cfg(all(not(debug_assertions), any(windows, target_os = "macos")))]
+    {
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    let ctx = env.top();
+    if ctx.is_generator() {
+        Some(ctx)
+    } else {
+        None
+    }
+}
+
+/// get the id of the current running generator
+pub fn current_id() -> Option<GeneratorId> {
+    current_generator().map(|ctx| GeneratorId(ctx.id))
+}
+
 /// get the current context local data
 /// only coroutine support local data
 #[cfg_attr(
@@ -370,6 +613,39 @@ mod test {
         assert!(!is_generator());
     }
 
+    #[test]
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    fn test_frame_record() {
+        use super::ContextStack;
+        use crate::*;
+
+        let mut g = Gn::<()>::new_scoped(|_| {
+            let env = ContextStack::current();
+            // the frame record at the stack bottom that the init frame links to
+            let end = env.top().stack_guard.1 & !15;
+            let record = unsafe { *(end as *const *const usize).sub(2) };
+            let root = unsafe { &(*env.root).regs } as *const _ as usize;
+            // it's the saved frame pointer and return address of the resumer
+            let offset = record as usize - root;
+            assert!(offset < std::mem::size_of::<crate::reg_context::RegContext>());
+            unsafe {
+                #[cfg(target_arch = "x86_64")]
+                {
+                    // the saved rsp is right before the saved rbp
+                    let sp = *record.sub(1) as *const usize;
+                    assert_eq!(*record.add(1), *sp);
+                }
+                assert_ne!(*record, 0);
+                assert_ne!(*record.add(1), 0);
+            }
+        });
+        g.resume();
+        assert!(g.is_done());
+    }
+
     #[test]
     fn test_overflow() {
         use crate::*;
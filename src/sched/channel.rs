@@ -0,0 +1,343 @@
+//! # multi producer multi consumer channels
+//!
+//! `send` on a full channel and `recv` on an empty channel park the current
+//! task instead of blocking the thread, the peer unparks it later. they work
+//! in the tasks of `Executor` and `Runtime` and block the thread out of the
+//! tasks, so a task can talk to a normal thread.
+//!
+//! the channel is disconnected when all the senders or all the receivers are
+//! dropped, or when `close` is called. the values already sent can still be
+//! received after it's disconnected.
+//!
+//! ```
+//! use generator::sched::{channel, Executor};
+//!
+//! let exec = Executor::new();
+//! let (tx, rx) = channel::bounded(1);
+//! exec.spawn(move || {
+//!     for i in 0..3 {
+//!         tx.send(i).unwrap();
+//!     }
+//! });
+//! let sum = exec.spawn(move || rx.iter().sum::<i32>());
+//! assert_eq!(sum.join().unwrap(), 3);
+//! ```
+//!
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub use std::sync::mpsc::{RecvError, SendError, TryRecvError, TrySendError};
+
+use super::park::Waiter;
+
+struct State<T> {
+    queue: VecDeque<T>,
+    cap: Option<usize>,
+    senders: usize,
+    receivers: usize,
+    closed: bool,
+    // the parked senders and receivers
+    send_waiters: VecDeque<Arc<Waiter>>,
+    recv_waiters: VecDeque<Arc<Waiter>>,
+}
+
+impl<T> State<T> {
+    fn is_disconnected(&self) -> bool {
+        self.closed || self.senders == 0 || self.receivers == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.cap.is_some_and(|cap| self.queue.len() >= cap)
+    }
+
+    // wake up all the waiters, they see the disconnection
+    fn disconnect(&mut self) {
+        for w in self
+            .send_waiters
+            .drain(..)
+            .chain(self.recv_waiters.drain(..))
+        {
+            w.wake();
+        }
+    }
+}
+
+struct Chan<T> {
+    state: Mutex<State<T>>,
+}
+
+impl<T> Chan<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+// wake up the first waiter, the woken one is dequeued
+fn wake_one(waiters: &mut VecDeque<Arc<Waiter>>) {
+    if let Some(w) = waiters.pop_front() {
+        w.wake();
+    }
+}
+
+// the waiter is cancelled, dequeue it or pass on the wake up it has taken
+fn cancel(waiters: &mut VecDeque<Arc<Waiter>>, me: &Arc<Waiter>, woken: bool) {
+    if woken {
+        wake_one(waiters);
+    } else {
+        waiters.retain(|w| !Arc::ptr_eq(w, me));
+    }
+}
+
+fn new<T>(cap: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Chan {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            cap,
+            senders: 1,
+            receivers: 1,
+            closed: false,
+            send_waiters: VecDeque::new(),
+            recv_waiters: VecDeque::new(),
+        }),
+    });
+    let tx = Sender { chan: chan.clone() };
+    (tx, Receiver { chan })
+}
+
+/// create a channel that holds at most `cap` values
+///
+/// # Panics
+///
+/// panics if `cap` is zero
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "the channel capacity must be positive");
+    new(Some(cap))
+}
+
+/// create a channel without the capacity limit, `send` never parks
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    new(None)
+}
+
+/// the sending side of a channel, it can be cloned for multiple producers
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Sender<T> {
+    /// send a value, park until there is room if the channel is full
+    ///
+    /// the value is returned back if the channel is disconnected
+    pub fn send(&self, v: T) -> Result<(), SendError<T>> {
+        let mut v = v;
+        loop {
+            match self.try_send(v) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(v)) => return Err(SendError(v)),
+                Err(TrySendError::Full(back)) => v = back,
+            }
+            let me = {
+                let mut state = self.chan.lock();
+                // it may be received after the `try_send`
+                if !state.is_full() || state.is_disconnected() {
+                    continue;
+                }
+                let me = Waiter::new();
+                state.send_waiters.push_back(me.clone());
+                me
+            };
+            me.wait(|woken| cancel(&mut self.chan.lock().send_waiters, &me, woken));
+        }
+    }
+
+    /// send a value without parking
+    pub fn try_send(&self, v: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.chan.lock();
+        if state.is_disconnected() {
+            return Err(TrySendError::Disconnected(v));
+        }
+        if state.is_full() {
+            return Err(TrySendError::Full(v));
+        }
+        state.queue.push_back(v);
+        wake_one(&mut state.recv_waiters);
+        // pass on the wake up that's taken by this one
+        if !state.is_full() {
+            wake_one(&mut state.send_waiters);
+        }
+        Ok(())
+    }
+
+    /// disconnect the channel, see `Receiver::close`
+    pub fn close(&self) {
+        let mut state = self.chan.lock();
+        state.closed = true;
+        state.disconnect();
+    }
+
+    /// is the channel disconnected
+    pub fn is_closed(&self) -> bool {
+        self.chan.lock().is_disconnected()
+    }
+
+    /// the number of values in the channel
+    pub fn len(&self) -> usize {
+        self.chan.lock().queue.len()
+    }
+
+    /// is the channel empty
+    pub fn is_empty(&self) -> bool {
+        self.chan.lock().queue.is_empty()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.lock().senders += 1;
+        Sender {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.chan.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.disconnect();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// the receiving side of a channel, it can be cloned for multiple consumers
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// receive a value, park until there is one if the channel is empty
+    ///
+    /// it fails if the channel is empty and disconnected
+    pub fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(v) => return Ok(v),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+            let me = {
+                let mut state = self.chan.lock();
+                // it may be sent after the `try_recv`
+                if !state.queue.is_empty() || state.is_disconnected() {
+                    continue;
+                }
+                let me = Waiter::new();
+                state.recv_waiters.push_back(me.clone());
+                me
+            };
+            me.wait(|woken| cancel(&mut self.chan.lock().recv_waiters, &me, woken));
+        }
+    }
+
+    /// receive a value without parking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.chan.lock();
+        match state.queue.pop_front() {
+            Some(v) => {
+                wake_one(&mut state.send_waiters);
+                // pass on the wake up that's taken by this one
+                if !state.queue.is_empty() {
+                    wake_one(&mut state.recv_waiters);
+                }
+                Ok(v)
+            }
+            None if state.is_disconnected() => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// disconnect the channel, the senders fail and the receivers get the
+    /// values left in the channel
+    pub fn close(&self) {
+        let mut state = self.chan.lock();
+        state.closed = true;
+        state.disconnect();
+    }
+
+    /// is the channel disconnected
+    pub fn is_closed(&self) -> bool {
+        self.chan.lock().is_disconnected()
+    }
+
+    /// the number of values in the channel
+    pub fn len(&self) -> usize {
+        self.chan.lock().queue.len()
+    }
+
+    /// is the channel empty
+    pub fn is_empty(&self) -> bool {
+        self.chan.lock().queue.is_empty()
+    }
+
+    /// an iterator receiving the values until the channel is disconnected
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.chan.lock().receivers += 1;
+        Receiver {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.chan.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            state.disconnect();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+/// the iterator returned by `Receiver::iter`
+#[derive(Debug)]
+pub struct Iter<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
@@ -0,0 +1,314 @@
+//! single threaded executor
+//!
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::panic;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use super::park::{park, unparker, Signal, Unparker, Wake};
+use super::{in_task, Slot, TaskGuard};
+use crate::gen_impl::{Generator, LocalGenerator, DEFAULT_STACK_SIZE};
+use crate::rt::Error;
+
+/// an executor running the tasks round-robin on the current thread
+///
+/// the parked tasks are not run until they are unparked, which can be done
+/// from other threads.
+///
+/// ```
+/// use generator::sched::{self, Executor};
+///
+/// let exec = Executor::new();
+/// let a = exec.spawn(|| {
+///     for _ in 0..3 {
+///         sched::yield_now();
+///     }
+///     1
+/// });
+/// let b = exec.spawn(|| 2);
+/// exec.run_until_idle();
+/// assert_eq!(a.join().unwrap() + b.join().unwrap(), 3);
+/// ```
+#[derive(Clone, Default)]
+pub struct Executor {
+    inner: Rc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    queue: RefCell<VecDeque<Task>>,
+    parked: RefCell<HashMap<u64, Task>>,
+    remote: Arc<Remote>,
+    running: Cell<bool>,
+}
+
+// the ids of the unparked tasks
+#[derive(Default)]
+struct Remote {
+    woken: Mutex<Vec<u64>>,
+    cv: Condvar,
+}
+
+impl Wake for Remote {
+    fn wake(&self, id: u64) {
+        self.woken.lock().unwrap().push(id);
+        self.cv.notify_one();
+    }
+}
+
+// deliver the end of the task to the join handle
+type Finish = Box<dyn FnOnce(thread::Result<()>)>;
+
+struct Task {
+    gen: LocalGenerator<'static, (), ()>,
+    signal: Arc<Signal>,
+    finish: Option<Finish>,
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        // the unfinished task is cancelled
+        if let Some(finish) = self.finish.take() {
+            finish(Err(Box::new(Error::Cancel)));
+        }
+    }
+}
+
+impl Executor {
+    /// create an executor
+    pub fn new() -> Self {
+        Executor::default()
+    }
+
+    /// spawn a task with default stack size
+    #[track_caller]
+    pub fn spawn<T, F>(&self, f: F) -> JoinHandle<T>
+    where
+        T: 'static,
+        F: FnOnce() -> T + 'static,
+    {
+        self.spawn_opt(DEFAULT_STACK_SIZE, f)
+    }
+
+    /// spawn a task with specified stack size
+    #[track_caller]
+    pub fn spawn_opt<T, F>(&self, size: usize, f: F) -> JoinHandle<T>
+    where
+        T: 'static,
+        F: FnOnce() -> T + 'static,
+    {
+        let packet = Rc::new(Packet::default());
+        let ret = packet.clone();
+        // the task yields by the raw `yield_with`
+        let gen = LocalGenerator::new_opt_unchecked(size, move || {
+            let v = f();
+            *ret.slot.borrow_mut() = Slot::Done(Ok(v));
+        });
+        let ret = packet.clone();
+        // the value is set by the task itself
+        self.push(
+            gen,
+            Box::new(move |r: thread::Result<()>| {
+                if let Err(e) = r {
+                    *ret.slot.borrow_mut() = Slot::Done(Err(e));
+                }
+                ret.finish();
+            }),
+        );
+        JoinHandle {
+            packet,
+            exec: Rc::downgrade(&self.inner),
+        }
+    }
+
+    /// spawn a generator as a task, it's resumed until it's done
+    ///
+    /// the generator can yield to switch to the other tasks, the same as
+    /// `yield_now`, and it can park in the `sched` primitives
+    pub fn spawn_generator(&self, gen: Generator<'static, (), ()>) -> JoinHandle<()> {
+        let packet = Rc::new(Packet::default());
+        let ret = packet.clone();
+        self.push(
+            gen.into_local(),
+            Box::new(move |r| {
+                *ret.slot.borrow_mut() = Slot::Done(r);
+                ret.finish();
+            }),
+        );
+        JoinHandle {
+            packet,
+            exec: Rc::downgrade(&self.inner),
+        }
+    }
+
+    fn push(&self, gen: LocalGenerator<'static, (), ()>, finish: Finish) {
+        let remote = Arc::downgrade(&self.inner.remote);
+        let signal = Signal::new(gen.id().as_u64(), remote);
+        self.inner.queue.borrow_mut().push_back(Task {
+            gen,
+            signal,
+            finish: Some(finish),
+        });
+    }
+
+    /// run the tasks until all of them are finished, including the newly spawned ones
+    ///
+    /// it blocks the thread when all the unfinished tasks are parked
+    ///
+    /// # Panics
+    ///
+    /// panics if the executor is already running
+    pub fn run_until_idle(&self) {
+        self.run_until(|| false);
+    }
+
+    /// the number of unfinished tasks
+    pub fn tasks(&self) -> usize {
+        self.inner.queue.borrow().len() + self.inner.parked.borrow().len()
+    }
+
+    /// is there no unfinished task
+    pub fn is_idle(&self) -> bool {
+        self.tasks() == 0
+    }
+
+    // move the unparked tasks back to the queue
+    fn unpark(&self, woken: Vec<u64>) {
+        let mut parked = self.inner.parked.borrow_mut();
+        let mut queue = self.inner.queue.borrow_mut();
+        queue.extend(woken.into_iter().filter_map(|id| parked.remove(&id)));
+    }
+
+    // run the tasks until `stop` returns true or there is no task
+    fn run_until(&self, mut stop: impl FnMut() -> bool) {
+        assert!(!self.inner.running.get(), "the executor is already running");
+        self.inner.running.set(true);
+        let _running = RunningGuard(&self.inner.running);
+
+        while !stop() {
+            let woken = std::mem::take(&mut *self.inner.remote.woken.lock().unwrap());
+            self.unpark(woken);
+            let task = self.inner.queue.borrow_mut().pop_front();
+            let Some(mut task) = task else {
+                if self.inner.parked.borrow().is_empty() {
+                    break;
+                }
+                // wait for the parked tasks
+                let mut woken = self.inner.remote.woken.lock().unwrap();
+                while woken.is_empty() {
+                    woken = self.inner.remote.cv.wait(woken).unwrap();
+                }
+                continue;
+            };
+            let (r, parking) = {
+                let guard = TaskGuard::enter(&task.signal);
+                let r = panic::catch_unwind(panic::AssertUnwindSafe(|| task.gen.resume()));
+                (r, guard.take_parking())
+            };
+            match r {
+                Err(e) => task.finish.take().unwrap()(Err(e)),
+                Ok(_) if task.gen.is_done() => task.finish.take().unwrap()(Ok(())),
+                // the woken ids are handled on this thread after it's inserted
+                Ok(_) if parking && task.signal.park() => {
+                    let id = task.signal.id();
+                    self.inner.parked.borrow_mut().insert(id, task);
+                }
+                Ok(_) => self.inner.queue.borrow_mut().push_back(task),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Executor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Executor")
+            .field("tasks", &self.tasks())
+            .finish()
+    }
+}
+
+struct RunningGuard<'a>(&'a Cell<bool>);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+/// the handle to get the result of a task
+///
+/// the result is `Err` with the panic payload if the task panics, or
+/// `Error::Cancel` if the task is dropped with the executor before it's finished
+pub struct JoinHandle<T> {
+    packet: Rc<Packet<T>>,
+    exec: Weak<Inner>,
+}
+
+// the result of a task and the tasks joining it
+struct Packet<T> {
+    slot: RefCell<Slot<T>>,
+    joiners: RefCell<Vec<Unparker>>,
+}
+
+impl<T> Default for Packet<T> {
+    fn default() -> Self {
+        Packet {
+            slot: RefCell::new(Slot::Pending),
+            joiners: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> Packet<T> {
+    // the task is finished, wake up the joining tasks
+    fn finish(&self) {
+        for joiner in self.joiners.take() {
+            joiner.unpark();
+        }
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// is the task finished
+    pub fn is_finished(&self) -> bool {
+        !matches!(*self.packet.slot.borrow(), Slot::Pending)
+    }
+
+    /// wait for the task and get the result
+    ///
+    /// in a task of the running executor it parks until the task is finished,
+    /// otherwise it runs the executor until the task is finished, which can
+    /// be done in a task of another executor
+    pub fn join(self) -> thread::Result<T> {
+        let exec = self.exec.upgrade();
+        if in_task() && exec.as_ref().map_or(true, |inner| inner.running.get()) {
+            let me = unparker();
+            while !self.is_finished() {
+                let mut joiners = self.packet.joiners.borrow_mut();
+                // not added twice after a spurious wake up
+                if !joiners.iter().any(|j| j.will_unpark(&me)) {
+                    joiners.push(me.clone());
+                }
+                drop(joiners);
+                park();
+            }
+        } else if let Some(inner) = exec {
+            Executor { inner }.run_until(|| self.is_finished());
+        }
+
+        // the executor is gone if it's still pending
+        self.packet.slot.borrow_mut().take()
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
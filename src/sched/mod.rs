@@ -0,0 +1,125 @@
+//! # generator scheduler
+//!
+//! run generator tasks cooperatively
+//!
+//! a task is a closure running in its own generator, it gives up the thread
+//! by `yield_now` and is resumed by the executor later. `Executor` runs
+//! the tasks round-robin on the current thread, `Runtime` runs the `Send`
+//! tasks on a pool of worker threads and a task may be resumed on any of them.
+//! a `Generator<'static, (), ()>` can also be spawned as a task by
+//! `Executor::spawn_generator`, its yields switch to the other tasks.
+//!
+//! a task waiting for something is suspended by `park` until it's unparked,
+//! the `channel` and the `sync` primitives are built on it.
+//!
+
+pub mod channel;
+mod executor;
+mod park;
+pub mod runtime;
+pub mod sync;
+
+use std::cell::{Cell, RefCell};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::thread;
+
+use self::park::Signal;
+use crate::rt::{current_id, Error};
+use crate::yield_::yield_with;
+
+pub use self::executor::{Executor, JoinHandle};
+pub use self::park::{park, unparker, Unparker};
+pub use self::runtime::Runtime;
+
+thread_local! {
+    // the generator id of the task that's resumed by an executor on this thread
+    static TASK: Cell<u64> = const { Cell::new(0) };
+    // the park state of the running task
+    static SIGNAL: RefCell<Option<Arc<Signal>>> = const { RefCell::new(None) };
+    // the running task yields by `park`
+    static PARKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// set the running task and restore the previous one when dropped
+///
+/// a task may be moved to another thread across a yield, so the accessors of
+/// the thread locals are not inlined and the values pass `black_box`, the same
+/// as `ContextStack::current`, otherwise the TLS address may be cached
+pub(crate) struct TaskGuard {
+    prev: u64,
+    prev_signal: Option<Arc<Signal>>,
+}
+
+impl TaskGuard {
+    #[inline(never)]
+    pub(crate) fn enter(signal: &Arc<Signal>) -> Self {
+        black_box(TaskGuard {
+            prev: TASK.with(|t| t.replace(signal.id())),
+            prev_signal: SIGNAL.with(|s| s.replace(Some(signal.clone()))),
+        })
+    }
+
+    /// did the task yield by `park`
+    #[inline(never)]
+    pub(crate) fn take_parking(&self) -> bool {
+        black_box(PARKING.with(|p| p.replace(false)))
+    }
+
+    #[inline(never)]
+    fn set_parking() {
+        PARKING.with(|p| p.set(black_box(true)));
+    }
+
+    #[inline(never)]
+    fn signal() -> Option<Arc<Signal>> {
+        black_box(SIGNAL.with(|s| s.borrow().clone()))
+    }
+}
+
+impl Drop for TaskGuard {
+    #[inline(never)]
+    fn drop(&mut self) {
+        TASK.with(|t| t.set(self.prev));
+        let prev = self.prev_signal.take();
+        SIGNAL.with(|s| *s.borrow_mut() = prev);
+    }
+}
+
+/// is it called from a task, not from the generators created in the task
+#[inline(never)]
+pub fn in_task() -> bool {
+    match current_id() {
+        Some(id) => black_box(TASK.with(|t| t.get())) == id.as_u64(),
+        None => false,
+    }
+}
+
+/// switch back to the executor, the task is resumed in its next turn
+///
+/// # Panics
+///
+/// panics if it's not called from a task
+#[track_caller]
+pub fn yield_now() {
+    assert!(in_task(), "yield_now is only possible in a task");
+    yield_with(());
+}
+
+/// the result slot of a task
+pub(crate) enum Slot<T> {
+    Pending,
+    Done(thread::Result<T>),
+    Taken,
+}
+
+impl<T> Slot<T> {
+    /// take the result, a pending task is cancelled
+    pub(crate) fn take(&mut self) -> thread::Result<T> {
+        match std::mem::replace(self, Slot::Taken) {
+            Slot::Done(r) => r,
+            Slot::Pending => Err(Box::new(Error::Cancel)),
+            Slot::Taken => unreachable!("the result is taken"),
+        }
+    }
+}
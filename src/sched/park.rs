@@ -0,0 +1,203 @@
+//! park and unpark the tasks
+//!
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+
+use super::{in_task, TaskGuard};
+use crate::yield_::yield_with;
+
+/// the executor that puts a parked task back to its run queue
+pub(crate) trait Wake: Send + Sync {
+    fn wake(&self, id: u64);
+}
+
+const IDLE: u8 = 0;
+const NOTIFIED: u8 = 1;
+const PARKED: u8 = 2;
+
+/// the park state of a task
+pub(crate) struct Signal {
+    id: u64,
+    state: AtomicU8,
+    wake: Weak<dyn Wake>,
+}
+
+impl Signal {
+    pub(crate) fn new(id: u64, wake: Weak<dyn Wake>) -> Arc<Self> {
+        Arc::new(Signal {
+            id,
+            state: AtomicU8::new(IDLE),
+            wake,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// the task yields by `park`, returns false if it's already unparked
+    /// and the executor should run it again
+    ///
+    /// the executor must be able to find the task by `Wake::wake` before this
+    pub(crate) fn park(&self) -> bool {
+        let parked = self
+            .state
+            .compare_exchange(IDLE, PARKED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if !parked {
+            // consume the notification
+            self.state.store(IDLE, Ordering::Release);
+        }
+        parked
+    }
+
+    fn unpark(&self) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let new = if state == PARKED { IDLE } else { NOTIFIED };
+            match self
+                .state
+                .compare_exchange(state, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(s) => state = s,
+            }
+        }
+        if state == PARKED {
+            // the executor may be gone, the task is cancelled then
+            if let Some(wake) = self.wake.upgrade() {
+                wake.wake(self.id);
+            }
+        }
+    }
+}
+
+/// wake up a parked task or thread, see `park`
+#[derive(Clone)]
+pub struct Unparker {
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Task(Arc<Signal>),
+    Thread(thread::Thread),
+}
+
+impl Unparker {
+    /// wake up the task or thread, if it's not parked the next `park` returns immediately
+    pub fn unpark(&self) {
+        match &self.inner {
+            Inner::Task(signal) => signal.unpark(),
+            Inner::Thread(t) => t.unpark(),
+        }
+    }
+
+    /// is it the same task or thread
+    pub fn will_unpark(&self, other: &Unparker) -> bool {
+        match (&self.inner, &other.inner) {
+            (Inner::Task(a), Inner::Task(b)) => Arc::ptr_eq(a, b),
+            (Inner::Thread(a), Inner::Thread(b)) => a.id() == b.id(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Unparker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.inner {
+            Inner::Task(signal) => f.debug_tuple("Task").field(&signal.id).finish(),
+            Inner::Thread(t) => f.debug_tuple("Thread").field(&t.id()).finish(),
+        }
+    }
+}
+
+/// get the unparker of the current task, or of the current thread if it's not in a task
+pub fn unparker() -> Unparker {
+    let inner = match TaskGuard::signal() {
+        Some(signal) if in_task() => Inner::Task(signal),
+        _ => Inner::Thread(thread::current()),
+    };
+    Unparker { inner }
+}
+
+/// suspend the current task until it's unparked, the executor runs the others
+/// meanwhile. it blocks the current thread by `thread::park` if it's not in a task.
+///
+/// it may return spuriously, check the condition in a loop
+///
+/// # Panics
+///
+/// panics in a generator created in a task, it can't yield to the executor
+/// and blocking the thread would block the executor with all its tasks
+pub fn park() {
+    let signal = match TaskGuard::signal() {
+        Some(signal) if in_task() => signal,
+        Some(_) => panic!("park in a generator nested in a task would block the executor"),
+        None => return thread::park(),
+    };
+    if signal
+        .state
+        .compare_exchange(NOTIFIED, IDLE, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+    {
+        return;
+    }
+    drop(signal);
+    TaskGuard::set_parking();
+    yield_with(());
+}
+
+/// a queued task or thread
+pub(crate) struct Waiter {
+    unparker: Unparker,
+    woken: AtomicBool,
+}
+
+impl Waiter {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Waiter {
+            unparker: unparker(),
+            woken: AtomicBool::new(false),
+        })
+    }
+
+    pub(crate) fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.unparker.unpark();
+    }
+
+    pub(crate) fn is_woken(&self) -> bool {
+        self.woken.load(Ordering::Acquire)
+    }
+
+    /// park until it's woken, `on_cancel` is called with `is_woken` if
+    /// the task is cancelled meanwhile, it must dequeue the waiter
+    pub(crate) fn wait(&self, on_cancel: impl FnOnce(bool)) {
+        let mut cancel = OnCancel {
+            waiter: self,
+            f: Some(on_cancel),
+        };
+        while !self.is_woken() {
+            park();
+        }
+        cancel.f = None;
+    }
+}
+
+struct OnCancel<'a, F: FnOnce(bool)> {
+    waiter: &'a Waiter,
+    f: Option<F>,
+}
+
+impl<F: FnOnce(bool)> Drop for OnCancel<'_, F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.f.take() {
+            f(self.waiter.is_woken());
+        }
+    }
+}
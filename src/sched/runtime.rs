@@ -0,0 +1,435 @@
+//! multi threaded runtime
+//!
+//! each worker thread has its own run queue, a yielded task goes back to the
+//! queue of the worker that resumed it and an idle worker steals the tasks
+//! from the others. the generator context is only linked into the context
+//! stack of a thread while it's running, so a task can be resumed on any
+//! worker. a task must not keep the references of the thread locals across
+//! `yield_now`.
+//!
+
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hint::black_box;
+use std::io;
+use std::panic;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use super::park::{park, unparker, Signal, Unparker, Wake};
+use super::{Slot, TaskGuard};
+use crate::gen_impl::{Generator, DEFAULT_STACK_SIZE};
+use crate::rt::Error;
+
+thread_local! {
+    // the runtime and the worker index of this thread
+    static WORKER: Cell<(*const Shared, usize)> = const { Cell::new((ptr::null(), 0)) };
+}
+
+// a task may be moved to another worker across a yield, see `TaskGuard`
+#[inline(never)]
+fn worker() -> (*const Shared, usize) {
+    black_box(WORKER.with(|w| w.get()))
+}
+
+#[inline(never)]
+fn set_worker(worker: (*const Shared, usize)) {
+    WORKER.with(|w| w.set(black_box(worker)));
+}
+
+// deliver the panic to the join handle
+type Fail = Box<dyn FnOnce(Box<dyn Any + Send>) + Send>;
+
+struct Task {
+    gen: Generator<'static, (), ()>,
+    signal: Arc<Signal>,
+    fail: Option<Fail>,
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        // the unfinished task is cancelled
+        if let Some(fail) = self.fail.take() {
+            fail(Box::new(Error::Cancel));
+        }
+    }
+}
+
+struct Shared {
+    // the tasks spawned from outside of the workers
+    injector: Mutex<VecDeque<Task>>,
+    // the run queues of the workers
+    queues: Vec<Mutex<VecDeque<Task>>>,
+    // the parked tasks by id
+    parked: Mutex<HashMap<u64, Task>>,
+    // the number of unfinished tasks
+    tasks: AtomicUsize,
+    // the wake up tokens for the sleeping workers
+    idle: Mutex<usize>,
+    idle_cv: Condvar,
+    shutdown: AtomicBool,
+}
+
+impl Shared {
+    fn push(&self, task: Task) {
+        let (shared, index) = worker();
+        if ptr::eq(shared, self) {
+            self.queues[index].lock().unwrap().push_back(task);
+        } else {
+            let mut injector = self.injector.lock().unwrap();
+            if self.shutdown.load(Ordering::Acquire) {
+                // the workers are gone, cancel it out of the lock
+                drop(injector);
+                self.tasks.fetch_sub(1, Ordering::Relaxed);
+                drop(task);
+                return;
+            }
+            injector.push_back(task);
+        }
+        self.notify();
+    }
+
+    fn notify(&self) {
+        let mut idle = self.idle.lock().unwrap();
+        // more tokens than workers only cause useless wake ups
+        if *idle < self.queues.len() {
+            *idle += 1;
+        }
+        self.idle_cv.notify_one();
+    }
+
+    fn pop(&self, index: usize) -> Option<Task> {
+        if let Some(task) = self.queues[index].lock().unwrap().pop_front() {
+            return Some(task);
+        }
+        if let Some(task) = self.injector.lock().unwrap().pop_front() {
+            return Some(task);
+        }
+        // steal from the other workers, start from the next one
+        let n = self.queues.len();
+        (1..n).find_map(|i| self.queues[(index + i) % n].lock().unwrap().pop_back())
+    }
+
+    fn sleep(&self) {
+        let mut idle = self.idle.lock().unwrap();
+        while *idle == 0 && !self.shutdown.load(Ordering::Acquire) {
+            idle = self.idle_cv.wait(idle).unwrap();
+        }
+        if *idle > 0 {
+            *idle -= 1;
+        }
+    }
+
+    fn run_worker(&self, index: usize) {
+        set_worker((self, index));
+        while !self.shutdown.load(Ordering::Acquire) {
+            let Some(mut task) = self.pop(index) else {
+                self.sleep();
+                continue;
+            };
+            let (r, parking) = {
+                let guard = TaskGuard::enter(&task.signal);
+                let r = panic::catch_unwind(panic::AssertUnwindSafe(|| task.gen.resume()));
+                (r, guard.take_parking())
+            };
+            match r {
+                Err(e) => task.fail.take().unwrap()(e),
+                Ok(_) if task.gen.is_done() => task.fail = None,
+                Ok(_) if parking => {
+                    self.park(index, task);
+                    continue;
+                }
+                Ok(_) => {
+                    self.queues[index].lock().unwrap().push_back(task);
+                    continue;
+                }
+            }
+            self.tasks.fetch_sub(1, Ordering::Relaxed);
+        }
+        set_worker((ptr::null(), 0));
+    }
+
+    fn park(&self, index: usize, task: Task) {
+        let signal = task.signal.clone();
+        // insert it first, `wake` must find it once it's parked
+        let mut parked = self.parked.lock().unwrap();
+        parked.insert(signal.id(), task);
+        if !signal.park() {
+            let task = parked.remove(&signal.id()).unwrap();
+            drop(parked);
+            self.queues[index].lock().unwrap().push_back(task);
+        }
+    }
+
+    // drop the left tasks after the workers are stopped
+    fn cancel_all(&self) {
+        let parked = std::mem::take(&mut *self.parked.lock().unwrap());
+        for (_, task) in parked {
+            self.tasks.fetch_sub(1, Ordering::Relaxed);
+            drop(task);
+        }
+        let injector = std::mem::take(&mut *self.injector.lock().unwrap());
+        for task in injector {
+            self.tasks.fetch_sub(1, Ordering::Relaxed);
+            drop(task);
+        }
+        for queue in &self.queues {
+            let queue = std::mem::take(&mut *queue.lock().unwrap());
+            for task in queue {
+                self.tasks.fetch_sub(1, Ordering::Relaxed);
+                drop(task);
+            }
+        }
+    }
+}
+
+/// a runtime running the `Send` tasks on the worker threads
+///
+/// dropping the runtime stops the workers after their current turn and
+/// cancels the unfinished tasks.
+///
+/// ```
+/// use generator::sched::{self, Runtime};
+///
+/// let rt = Runtime::new(2).unwrap();
+/// let handle = rt.handle();
+/// let a = rt.spawn(move || {
+///     let b = handle.spawn(|| 1);
+///     sched::yield_now();
+///     b.join().unwrap() + 1
+/// });
+/// assert_eq!(a.join().unwrap(), 2);
+/// ```
+pub struct Runtime {
+    handle: Handle,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Runtime {
+    /// create a runtime with `workers` worker threads
+    ///
+    /// # Panics
+    ///
+    /// panics if `workers` is zero
+    pub fn new(workers: usize) -> io::Result<Runtime> {
+        assert!(workers > 0, "the runtime needs at least one worker");
+        let shared = Arc::new(Shared {
+            injector: Mutex::new(VecDeque::new()),
+            queues: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            parked: Mutex::new(HashMap::new()),
+            tasks: AtomicUsize::new(0),
+            idle: Mutex::new(0),
+            idle_cv: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+        });
+        let mut rt = Runtime {
+            handle: Handle { shared },
+            workers: Vec::with_capacity(workers),
+        };
+        for index in 0..workers {
+            let shared = rt.handle.shared.clone();
+            // the started workers are stopped by drop on error
+            let worker = thread::Builder::new()
+                .name(format!("generator-worker-{index}"))
+                .spawn(move || shared.run_worker(index))?;
+            rt.workers.push(worker);
+        }
+        Ok(rt)
+    }
+
+    /// get a handle to spawn the tasks, it can be moved into the tasks
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    /// spawn a task with default stack size
+    #[track_caller]
+    pub fn spawn<T, F>(&self, f: F) -> JoinHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.handle.spawn(f)
+    }
+
+    /// spawn a task with specified stack size
+    #[track_caller]
+    pub fn spawn_opt<T, F>(&self, size: usize, f: F) -> JoinHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.handle.spawn_opt(size, f)
+    }
+
+    /// the number of unfinished tasks
+    pub fn tasks(&self) -> usize {
+        self.handle.tasks()
+    }
+
+    /// the number of worker threads
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        let shared = &self.handle.shared;
+        // set it in the lock, no task is pushed to the injector afterwards
+        let injector = shared.injector.lock().unwrap();
+        shared.shutdown.store(true, Ordering::Release);
+        drop(injector);
+        {
+            let _idle = shared.idle.lock().unwrap();
+            shared.idle_cv.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        shared.cancel_all();
+    }
+}
+
+impl fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Runtime")
+            .field("workers", &self.workers())
+            .field("tasks", &self.tasks())
+            .finish()
+    }
+}
+
+/// a handle of the runtime to spawn the tasks
+///
+/// a task spawned from a worker goes to the run queue of that worker. after
+/// the runtime is dropped the spawned task is cancelled immediately.
+#[derive(Clone)]
+pub struct Handle {
+    shared: Arc<Shared>,
+}
+
+impl Handle {
+    /// spawn a task with default stack size
+    #[track_caller]
+    pub fn spawn<T, F>(&self, f: F) -> JoinHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.spawn_opt(DEFAULT_STACK_SIZE, f)
+    }
+
+    /// spawn a task with specified stack size
+    #[track_caller]
+    pub fn spawn_opt<T, F>(&self, size: usize, f: F) -> JoinHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let packet = Arc::new(Packet {
+            slot: Mutex::new((Slot::Pending, Vec::new())),
+        });
+        let ret = packet.clone();
+        // the task yields by the raw `yield_with`
+        let gen = Generator::new_opt_unchecked(size, move || {
+            let v = f();
+            ret.set(Ok(v));
+        });
+        let ret = packet.clone();
+        let fail: Fail = Box::new(move |e| ret.set(Err(e)));
+        let shared = Arc::downgrade(&self.shared);
+        let signal = Signal::new(gen.id().as_u64(), shared);
+        self.shared.tasks.fetch_add(1, Ordering::Relaxed);
+        self.shared.push(Task {
+            gen,
+            signal,
+            fail: Some(fail),
+        });
+        JoinHandle { packet }
+    }
+
+    /// the number of unfinished tasks
+    pub fn tasks(&self) -> usize {
+        self.shared.tasks.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("tasks", &self.tasks())
+            .finish()
+    }
+}
+
+struct Packet<T> {
+    // the result and the joiners
+    slot: Mutex<(Slot<T>, Vec<Unparker>)>,
+}
+
+impl<T> Packet<T> {
+    fn set(&self, r: thread::Result<T>) {
+        let mut slot = self.slot.lock().unwrap();
+        slot.0 = Slot::Done(r);
+        for joiner in slot.1.drain(..) {
+            joiner.unpark();
+        }
+    }
+}
+
+/// the handle to get the result of a task spawned on a `Runtime`
+///
+/// the result is `Err` with the panic payload if the task panics, or
+/// `Error::Cancel` if the task is cancelled by dropping the runtime
+pub struct JoinHandle<T> {
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// is the task finished
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.packet.slot.lock().unwrap().0, Slot::Pending)
+    }
+
+    /// wait for the task and get the result
+    ///
+    /// in a task it parks until the task is finished, otherwise it blocks
+    /// the current thread
+    pub fn join(self) -> thread::Result<T> {
+        let me = unparker();
+        loop {
+            {
+                let mut slot = self.packet.slot.lock().unwrap();
+                if !matches!(slot.0, Slot::Pending) {
+                    return slot.0.take();
+                }
+                if !slot.1.iter().any(|u| u.will_unpark(&me)) {
+                    slot.1.push(me.clone());
+                }
+            }
+            park();
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+impl Wake for Shared {
+    fn wake(&self, id: u64) {
+        let task = self.parked.lock().unwrap().remove(&id);
+        if let Some(task) = task {
+            self.push(task);
+        }
+    }
+}
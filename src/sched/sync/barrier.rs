@@ -0,0 +1,72 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use super::Waiter;
+
+struct State {
+    count: usize,
+    waiters: Vec<Arc<Waiter>>,
+}
+
+/// a barrier that lets `n` tasks wait for each other
+pub struct Barrier {
+    n: usize,
+    state: Mutex<State>,
+}
+
+/// the result of `Barrier::wait`
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// is it the last one arrived, there is exactly one leader for each round
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// create a barrier for `n` tasks, it never parks if `n` is 0 or 1
+    pub const fn new(n: usize) -> Self {
+        Barrier {
+            n,
+            state: Mutex::new(State {
+                count: 0,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    /// park until all the `n` tasks are waiting, then all of them are resumed
+    pub fn wait(&self) -> BarrierWaitResult {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            state.count += 1;
+            if state.count >= self.n {
+                state.count = 0;
+                for w in state.waiters.drain(..) {
+                    w.wake();
+                }
+                return BarrierWaitResult(true);
+            }
+            let waiter = Waiter::new();
+            state.waiters.push(waiter.clone());
+            waiter
+        };
+        waiter.wait(|woken| {
+            if !woken {
+                // leave the round
+                let mut state = self.state.lock().unwrap();
+                state.count -= 1;
+                state.waiters.retain(|w| !Arc::ptr_eq(w, &waiter));
+            }
+        });
+        BarrierWaitResult(false)
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Barrier").field("n", &self.n).finish()
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use super::{MutexGuard, Waiter};
+
+/// a condition variable working with the `Mutex` of this module
+pub struct Condvar {
+    waiters: StdMutex<VecDeque<Arc<Waiter>>>,
+}
+
+impl Condvar {
+    /// create a condition variable
+    pub const fn new() -> Self {
+        Condvar {
+            waiters: StdMutex::new(VecDeque::new()),
+        }
+    }
+
+    /// unlock the mutex and park until it's notified, the mutex is locked
+    /// again before it returns
+    ///
+    /// it may return without the condition changed, check it in a loop or
+    /// use `wait_while`
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        let waiter = Waiter::new();
+        self.waiters.lock().unwrap().push_back(waiter.clone());
+        drop(guard);
+        waiter.wait(|woken| {
+            if woken {
+                // pass on the notification
+                self.notify_one();
+            } else {
+                let mut waiters = self.waiters.lock().unwrap();
+                waiters.retain(|w| !Arc::ptr_eq(w, &waiter));
+            }
+        });
+        mutex.lock()
+    }
+
+    /// wait while the `condition` returns true
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// wake up the first waiter
+    pub fn notify_one(&self) {
+        let waiter = self.waiters.lock().unwrap().pop_front();
+        if let Some(w) = waiter {
+            w.wake();
+        }
+    }
+
+    /// wake up all the waiters
+    pub fn notify_all(&self) {
+        let waiters = std::mem::take(&mut *self.waiters.lock().unwrap());
+        for w in waiters {
+            w.wake();
+        }
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Condvar::new()
+    }
+}
+
+impl fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Condvar").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,24 @@
+//! # synchronization primitives for the tasks
+//!
+//! the waiting tasks are parked and queued, they are resumed in FIFO order.
+//! out of the tasks the waiting blocks the current thread instead, but a
+//! generator created in a task panics when it has to wait, see `park`.
+//!
+//! the locks are not poisoned, a panic in the task just releases it.
+//!
+
+mod barrier;
+mod condvar;
+mod mutex;
+mod notify;
+mod rwlock;
+mod semaphore;
+
+use super::park::Waiter;
+
+pub use self::barrier::{Barrier, BarrierWaitResult};
+pub use self::condvar::Condvar;
+pub use self::mutex::{Mutex, MutexGuard};
+pub use self::notify::Notify;
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use self::semaphore::{Semaphore, SemaphorePermit};
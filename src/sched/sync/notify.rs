@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use super::Waiter;
+
+struct State {
+    // a `notify_one` without any waiter
+    permit: bool,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+/// notify the waiting tasks
+///
+/// `notify_one` without any waiter is kept, the next `wait` returns
+/// immediately. `notify_all` only wakes up the current waiters.
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+impl Notify {
+    /// create a notify
+    pub const fn new() -> Self {
+        Notify {
+            state: Mutex::new(State {
+                permit: false,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// park until it's notified
+    pub fn wait(&self) {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            if state.permit {
+                state.permit = false;
+                return;
+            }
+            let waiter = Waiter::new();
+            state.waiters.push_back(waiter.clone());
+            waiter
+        };
+        waiter.wait(|woken| {
+            if woken {
+                // pass on the notification
+                self.notify_one();
+            } else {
+                let mut state = self.state.lock().unwrap();
+                state.waiters.retain(|w| !Arc::ptr_eq(w, &waiter));
+            }
+        });
+    }
+
+    /// wake up the first waiter, or let the next `wait` return immediately
+    pub fn notify_one(&self) {
+        let mut state = self.state.lock().unwrap();
+        match state.waiters.pop_front() {
+            Some(w) => w.wake(),
+            None => state.permit = true,
+        }
+    }
+
+    /// wake up all the current waiters
+    pub fn notify_all(&self) {
+        let waiters = std::mem::take(&mut self.state.lock().unwrap().waiters);
+        for w in waiters {
+            w.wake();
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Notify::new()
+    }
+}
+
+impl fmt::Debug for Notify {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Notify").finish_non_exhaustive()
+    }
+}
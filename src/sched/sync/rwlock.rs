@@ -0,0 +1,145 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use super::Semaphore;
+
+// a writer takes all the permits, a reader takes one
+const MAX_READS: usize = u32::MAX as usize >> 3;
+
+/// a reader-writer lock parking the waiting tasks
+///
+/// the readers and writers are queued together, a waiting writer blocks
+/// the readers coming after it.
+pub struct RwLock<T: ?Sized> {
+    sem: Semaphore,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// create a rwlock
+    pub const fn new(v: T) -> Self {
+        RwLock {
+            sem: Semaphore::new(MAX_READS),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// get the data
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// lock for shared read access, park while it's locked by a writer
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.sem.acquire().forget();
+        RwLockReadGuard { lock: self }
+    }
+
+    /// lock for exclusive write access, park while it's locked
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.sem.acquire_many(MAX_READS).forget();
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// lock for read without parking
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.sem.try_acquire()?.forget();
+        Some(RwLockReadGuard { lock: self })
+    }
+
+    /// lock for write without parking
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.sem.try_acquire_many(MAX_READS)?.forget();
+        Some(RwLockWriteGuard { lock: self })
+    }
+
+    /// get the data by a mutable reference, no locking is needed
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+/// the guard of the shared read access
+#[must_use = "the rwlock is unlocked immediately if the guard is not used"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.sem.add_permits(1);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// the guard of the exclusive write access
+#[must_use = "the rwlock is unlocked immediately if the guard is not used"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.sem.add_permits(MAX_READS);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
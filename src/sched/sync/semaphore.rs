@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use super::Waiter;
+
+struct State {
+    permits: usize,
+    // the waiters and the permits they need
+    waiters: VecDeque<(usize, Arc<Waiter>)>,
+}
+
+impl State {
+    // hand the permits over to the waiters in order
+    fn grant(&mut self) {
+        while let Some((n, _)) = self.waiters.front() {
+            if *n > self.permits {
+                break;
+            }
+            self.permits -= *n;
+            let (_, waiter) = self.waiters.pop_front().unwrap();
+            waiter.wake();
+        }
+    }
+}
+
+/// a counting semaphore
+///
+/// the permits are handed over to the waiters in FIFO order, a new acquirer
+/// can't take the permits while there are waiters before it.
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+impl Semaphore {
+    /// create a semaphore with `permits` permits
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// acquire a permit, park until it's available
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// acquire `n` permits at once, park until they are available
+    pub fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            if state.waiters.is_empty() && state.permits >= n {
+                state.permits -= n;
+                return SemaphorePermit { sem: self, n };
+            }
+            let waiter = Waiter::new();
+            state.waiters.push_back((n, waiter.clone()));
+            waiter
+        };
+        waiter.wait(|woken| {
+            let mut state = self.state.lock().unwrap();
+            if woken {
+                // give back the granted permits
+                state.permits += n;
+            } else {
+                state.waiters.retain(|(_, w)| !Arc::ptr_eq(w, &waiter));
+            }
+            // the waiters after it may be satisfied now
+            state.grant();
+        });
+        SemaphorePermit { sem: self, n }
+    }
+
+    /// acquire a permit without parking
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// acquire `n` permits without parking
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.waiters.is_empty() && state.permits >= n {
+            state.permits -= n;
+            return Some(SemaphorePermit { sem: self, n });
+        }
+        None
+    }
+
+    /// add `n` permits to the semaphore
+    pub fn add_permits(&self, n: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.permits += n;
+        state.grant();
+    }
+
+    /// the number of the available permits
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("Semaphore")
+            .field("permits", &state.permits)
+            .field("waiters", &state.waiters.len())
+            .finish()
+    }
+}
+
+/// the acquired permits, they are released when it's dropped
+#[must_use = "the permits are released immediately if it's not used"]
+pub struct SemaphorePermit<'a> {
+    sem: &'a Semaphore,
+    n: usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// keep the permits acquired, they are not released
+    pub fn forget(self) {
+        std::mem::forget(self)
+    }
+
+    /// the number of the permits
+    pub fn num_permits(&self) -> usize {
+        self.n
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.sem.add_permits(self.n);
+    }
+}
+
+impl fmt::Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.n)
+            .finish()
+    }
+}
@@ -4,6 +4,8 @@
 //!
 
 use std::marker::PhantomData;
+use std::panic;
+use std::ptr;
 use std::sync::atomic;
 
 use crate::gen_impl::Generator;
@@ -37,6 +39,7 @@ impl<'a, A, T> Scope<'_, 'a, A, T> {
 
     /// raw yield without catch passed in para
     #[inline]
+    #[track_caller]
     fn raw_yield(&mut self, env: &ContextStack, context: &mut Context, v: T) {
         // check the context
         if !context.is_generator() {
@@ -49,18 +52,40 @@ impl<'a, A, T> Scope<'_, 'a, A, T> {
 
         // here we just panic to exit the func
         if context._ref != 1 {
-            std::panic::panic_any(Error::Cancel);
+            std::panic::resume_unwind(Box::new(Error::Cancel));
         }
     }
 
     /// yield something without catch passed in para
     #[inline]
+    #[track_caller]
     pub fn yield_with(&mut self, v: T) {
         let env = ContextStack::current();
         let context = env.top();
         self.raw_yield(&env, context, v);
     }
 
+    /// check if the generator is being closed by `Generator::close`
+    /// when it's true the yield returns `None`, the generator should do the cleanup
+    /// and return the last value, yield again would cancel the generator
+    #[inline]
+    pub fn is_closing(&self) -> bool {
+        ContextStack::current().top().is_closing()
+    }
+
+    /// register a cleanup function that runs when the generator is finished
+    ///
+    /// the deferred functions run in LIFO order after the generator function
+    /// returns, panics or gets cancelled, just like the drop of a local guard,
+    /// but without the need of keeping the guard alive across the yields.
+    /// the functions are allocated in an area reserved at the top of the
+    /// generator stack when it's created, 1/16 of the stack and at most 256
+    /// words, they only go to the heap when the area is full
+    pub fn defer<F: FnOnce() + Send + 'a>(&mut self, f: F) {
+        // the defers are always consumed before the generator is dropped
+        unsafe { ContextStack::current().top().push_defer(f) };
+    }
+
     /// get current generator send para
     #[inline]
     pub fn get_yield(&mut self) -> Option<A> {
@@ -72,6 +97,7 @@ impl<'a, A, T> Scope<'_, 'a, A, T> {
     /// When yield out, the reference of the captured data must be still valid
     /// normally, you should always call the `drop` of the generator
     #[inline]
+    #[track_caller]
     pub unsafe fn yield_unsafe(&mut self, v: T) -> Option<A> {
         self.yield_with(v);
         atomic::compiler_fence(atomic::Ordering::Acquire);
@@ -83,20 +109,109 @@ impl<'a, A, T> Scope<'_, 'a, A, T> {
     /// # Safety
     /// When yield out, the reference of the captured data must be still valid
     /// normally, you should always call the `drop` of the generator
+    #[track_caller]
     pub unsafe fn yield_from_unsafe(&mut self, mut g: Generator<A, T>) -> Option<A> {
         let env = ContextStack::current();
         let context = env.top();
+        // the parent resumes the delegate directly, except for coroutines
+        // that need the generator stay on the context stack
+        let direct = context.local_data.is_null();
+        let d = g.as_delegate();
+        (*d).delegator = context;
         let mut p = self.get_yield();
         while !g.is_done() {
             match g.raw_send(p) {
                 None => return None,
-                Some(r) => self.raw_yield(&env, context, r),
+                Some(r) => {
+                    if direct {
+                        context.delegate = d;
+                    }
+                    self.raw_yield(&env, context, r);
+                    context.delegate = ptr::null_mut();
+                }
+            }
+            // the delegate may panic when it's resumed directly
+            if let Some(e) = g.get_panic_data() {
+                panic::resume_unwind(e);
             }
             p = self.get_yield();
         }
         drop(g); // explicitly consume g
         p
     }
+
+    /// `yield_from_map_unsafe`
+    /// delegate to a generator with different types, the yielded items
+    /// are converted by `map_out` and the send paras by `map_in`
+    /// # Safety
+    /// When yield out, the reference of the captured data must be still valid
+    /// normally, you should always call the `drop` of the generator
+    #[track_caller]
+    pub unsafe fn yield_from_map_unsafe<B, U, F, G>(
+        &mut self,
+        mut g: Generator<B, U>,
+        mut map_out: F,
+        mut map_in: G,
+    ) -> Option<A>
+    where
+        F: FnMut(U) -> T,
+        G: FnMut(A) -> B,
+    {
+        let env = ContextStack::current();
+        let context = env.top();
+        // the delegate sees the closing flag, see `is_closing`
+        (*g.as_delegate()).delegator = context;
+        let mut p = self.get_yield();
+        while !g.is_done() {
+            match g.raw_send(p.map(&mut map_in)) {
+                None => return None,
+                Some(r) => self.raw_yield(&env, context, map_out(r)),
+            }
+            p = self.get_yield();
+        }
+        drop(g); // explicitly consume g
+        p
+    }
+
+    /// `yield_all_unsafe`
+    /// yield all the items of the iterator and return the last send para
+    /// # Safety
+    /// When yield out, the reference of the captured data must be still valid
+    /// normally, you should always call the `drop` of the generator
+    #[track_caller]
+    pub unsafe fn yield_all_unsafe<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Option<A> {
+        let env = ContextStack::current();
+        let context = env.top();
+        let mut p = self.get_yield();
+        for v in iter {
+            self.raw_yield(&env, context, v);
+            p = self.get_yield();
+        }
+        p
+    }
+
+    /// `yield_from_ret_unsafe`
+    /// forward the items of the sub generator and evaluate to its return value
+    /// the return value is not yielded out, `None` means it's finished by `done!()`
+    /// # Safety
+    /// When yield out, the reference of the captured data must be still valid
+    /// normally, you should always call the `drop` of the generator
+    #[track_caller]
+    pub unsafe fn yield_from_ret_unsafe(&mut self, mut g: Generator<A, T>) -> Option<T> {
+        let env = ContextStack::current();
+        let context = env.top();
+        // the delegate sees the closing flag, see `is_closing`
+        (*g.as_delegate()).delegator = context;
+        let mut p = self.get_yield();
+        loop {
+            let r = g.raw_send(p)?;
+            if g.is_done() {
+                return Some(r);
+            }
+            self.raw_yield(&env, context, r);
+            p = self.get_yield();
+        }
+    }
 }
 
 impl<A, T> Scope<'_, 'static, A, T> {
@@ -104,13 +219,47 @@ impl<A, T> Scope<'_, 'static, A, T> {
     // it's totally safe that we can refer to the function block
     // since we will come back later
     #[inline]
+    #[track_caller]
     pub fn yield_(&mut self, v: T) -> Option<A> {
         unsafe { self.yield_unsafe(v) }
     }
 
     /// `yield_from`
     /// the from generator must has the same type as itself
+    #[track_caller]
     pub fn yield_from(&mut self, g: Generator<A, T>) -> Option<A> {
         unsafe { self.yield_from_unsafe(g) }
     }
+
+    /// `yield_from_map`
+    /// delegate to a generator with different types, the yielded items
+    /// are converted by `map_out` and the send paras by `map_in`
+    #[track_caller]
+    pub fn yield_from_map<B, U, F, G>(
+        &mut self,
+        g: Generator<B, U>,
+        map_out: F,
+        map_in: G,
+    ) -> Option<A>
+    where
+        F: FnMut(U) -> T,
+        G: FnMut(A) -> B,
+    {
+        unsafe { self.yield_from_map_unsafe(g, map_out, map_in) }
+    }
+
+    /// `yield_all`
+    /// yield all the items of the iterator and return the last send para
+    #[track_caller]
+    pub fn yield_all<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Option<A> {
+        unsafe { self.yield_all_unsafe(iter) }
+    }
+
+    /// `yield_from_ret`
+    /// like `yield from` in python, the sub generator's return value
+    /// is not yielded out but returned
+    #[track_caller]
+    pub fn yield_from_ret(&mut self, g: Generator<A, T>) -> Option<T> {
+        unsafe { self.yield_from_ret_unsafe(g) }
+    }
 }
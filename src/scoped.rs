@@ -0,0 +1,240 @@
+//! # scoped generators
+//!
+//! generators that can safely yield with borrowed data
+//!
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::NonNull;
+
+use crate::gen_impl::{LocalGenerator, DEFAULT_STACK_SIZE};
+use crate::scope::Scope;
+
+// type erased generator slot owned by the scope
+trait Slot {
+    fn cancel(&mut self);
+}
+
+impl<A, T> Slot for LocalGenerator<'_, A, T> {
+    fn cancel(&mut self) {
+        LocalGenerator::cancel(self)
+    }
+}
+
+type Slots<'scope> = RefCell<Vec<Box<dyn Slot + 'scope>>>;
+
+/// create a scope for spawning generators that borrow from the caller
+///
+/// all the generators spawned in the scope are finished or cancelled
+/// before this function returns, even when the closure panics. so it's
+/// safe for them to yield while holding data borrowed from the environment.
+///
+/// ```
+/// let mut v = vec![1, 2, 3];
+/// let sum = generator::scope(|s| {
+///     let mut g = s.spawn(|mut sc| {
+///         for x in v.iter_mut() {
+///             *x *= 2;
+///             sc.yield_(*x);
+///         }
+///         0
+///     });
+///     g.by_ref().sum::<i32>()
+/// });
+/// assert_eq!(sum, 12);
+/// assert_eq!(v, [2, 4, 6]);
+/// ```
+pub fn scope<'env, F, R>(f: F) -> R
+where
+    F: for<'scope> FnOnce(&'scope GeneratorScope<'scope, 'env>) -> R,
+{
+    let scope = GeneratorScope {
+        slots: ManuallyDrop::new(RefCell::new(Vec::new())),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    let cancel = scope.cancel_all();
+
+    match (ret, cancel) {
+        (Err(e), _) | (Ok(_), Err(e)) => panic::resume_unwind(e),
+        (Ok(r), Ok(())) => r,
+    }
+}
+
+/// the scope to spawn generators, created by `generator::scope`
+pub struct GeneratorScope<'scope, 'env: 'scope> {
+    // always emptied by `cancel_all` before the scope is dropped
+    slots: ManuallyDrop<Slots<'scope>>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope> GeneratorScope<'scope, '_> {
+    /// spawn a generator with default stack size in the scope
+    #[track_caller]
+    pub fn spawn<A, T, F>(&'scope self, f: F) -> ScopedGenerator<'scope, A, T>
+    where
+        for<'s> F: FnOnce(ScopedYield<'s, 'scope, A, T>) -> T + 'scope,
+        A: 'scope,
+        T: 'scope,
+    {
+        self.spawn_opt(DEFAULT_STACK_SIZE, f)
+    }
+
+    /// spawn a generator with specified stack size in the scope
+    #[track_caller]
+    pub fn spawn_opt<A, T, F>(&'scope self, size: usize, f: F) -> ScopedGenerator<'scope, A, T>
+    where
+        for<'s> F: FnOnce(ScopedYield<'s, 'scope, A, T>) -> T + 'scope,
+        A: 'scope,
+        T: 'scope,
+    {
+        let gen = LocalGenerator::new_scoped_done_unchecked(
+            size,
+            move |scope: Scope<'_, 'scope, A, T>| Some(f(ScopedYield { scope })),
+        );
+        // the box address is stable until the scope ends
+        let mut gen = Box::new(gen);
+        let ptr = NonNull::from(&mut *gen);
+        self.slots.borrow_mut().push(gen);
+        ScopedGenerator {
+            gen: ptr,
+            scope: PhantomData,
+        }
+    }
+
+    // cancel all the generators and then free them
+    fn cancel_all(&self) -> std::thread::Result<()> {
+        let mut ret = Ok(());
+        let mut len = 0;
+        // the cancelled generators may spawn new ones, repeat until no new one comes
+        while len != self.slots.borrow().len() {
+            len = self.slots.borrow().len();
+            for i in (0..len).rev() {
+                // don't hold the borrow, the cancel would access the slots again
+                let slot: *mut (dyn Slot + 'scope) = &mut *self.slots.borrow_mut()[i];
+                let r = panic::catch_unwind(AssertUnwindSafe(|| unsafe { (*slot).cancel() }));
+                if ret.is_ok() {
+                    ret = r;
+                }
+            }
+        }
+
+        let slots = std::mem::take(&mut *self.slots.borrow_mut());
+        drop(slots);
+        ret
+    }
+}
+
+/// the handle of a generator spawned in a `GeneratorScope`
+///
+/// dropping the handle cancels the generator, the memory is
+/// reclaimed when the scope ends
+pub struct ScopedGenerator<'scope, A, T> {
+    gen: NonNull<LocalGenerator<'scope, A, T>>,
+    scope: PhantomData<&'scope LocalGenerator<'scope, A, T>>,
+}
+
+impl<A, T> ScopedGenerator<'_, A, T> {
+    #[inline]
+    fn gen_mut(&mut self) -> &mut LocalGenerator<'_, A, T> {
+        let gen = unsafe { &mut *self.gen.as_ptr() };
+        // the handle may be captured by the generator itself
+        assert!(!gen.is_running(), "scoped generator is already running");
+        gen
+    }
+
+    /// prepare the para that passed into generator before send
+    #[inline]
+    pub fn set_para(&mut self, para: A) {
+        self.gen_mut().set_para(para)
+    }
+
+    /// resume the generator without touch the para
+    /// you should call `set_para` before this method
+    #[inline]
+    pub fn resume(&mut self) -> Option<T> {
+        self.gen_mut().resume()
+    }
+
+    /// `raw_send`
+    #[inline]
+    pub fn raw_send(&mut self, para: Option<A>) -> Option<T> {
+        self.gen_mut().raw_send(para)
+    }
+
+    /// send interface
+    pub fn send(&mut self, para: A) -> T {
+        self.gen_mut().send(para)
+    }
+
+    /// cancel the generator
+    pub fn cancel(&mut self) {
+        self.gen_mut().cancel()
+    }
+
+    /// close the generator gracefully, see `Generator::close`
+    pub fn close(&mut self) -> Option<T> {
+        self.gen_mut().close()
+    }
+}
+
+impl<'scope, A, T> Deref for ScopedGenerator<'scope, A, T> {
+    type Target = LocalGenerator<'scope, A, T>;
+
+    // no `DerefMut`, the generator must not be moved out of the scope
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.gen.as_ref() }
+    }
+}
+
+impl<T> Iterator for ScopedGenerator<'_, (), T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.resume()
+    }
+}
+
+impl<A, T> Drop for ScopedGenerator<'_, A, T> {
+    fn drop(&mut self) {
+        let gen = unsafe { &mut *self.gen.as_ptr() };
+        if !gen.is_running() {
+            gen.cancel();
+        }
+    }
+}
+
+/// the scope passed to a generator spawned in a `GeneratorScope`
+///
+/// the generator is always finished before the borrowed data goes away,
+/// so yield is safe here
+pub struct ScopedYield<'s, 'scope, A, T> {
+    scope: Scope<'s, 'scope, A, T>,
+}
+
+impl<'scope, A, T> ScopedYield<'_, 'scope, A, T> {
+    /// yield and get the send para
+    #[inline]
+    pub fn yield_(&mut self, v: T) -> Option<A> {
+        unsafe { self.scope.yield_unsafe(v) }
+    }
+}
+
+impl<'s, 'scope, A, T> Deref for ScopedYield<'s, 'scope, A, T> {
+    type Target = Scope<'s, 'scope, A, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.scope
+    }
+}
+
+impl<A, T> DerefMut for ScopedYield<'_, '_, A, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.scope
+    }
+}
@@ -15,6 +15,9 @@ pub mod sys;
 
 pub use sys::overflow;
 
+// the bytes below the stack pointer that may be still in use
+const RED_ZONE: usize = 512;
+
 // must align with StackBoxHeader
 const ALIGN: usize = std::mem::size_of::<StackBoxHeader>();
 const HEADER_SIZE: usize = std::mem::size_of::<StackBoxHeader>() / std::mem::size_of::<usize>();
@@ -35,6 +38,14 @@ pub struct StackBox<T> {
 }
 
 impl<T> StackBox<T> {
+    /// the words a box of `T` takes at most, including the header
+    fn max_words() -> usize {
+        let layout = std::alloc::Layout::new::<T>();
+        let align = std::cmp::max(layout.align(), ALIGN);
+        let size = ((layout.size() + align - 1) & !(align - 1)) / std::mem::size_of::<usize>();
+        size + align / std::mem::size_of::<usize>() + HEADER_SIZE
+    }
+
     /// create uninit stack box
     fn new_uninit(stack: &mut Stack, need_drop: usize) -> MaybeUninit<Self> {
         // cheat #[warn(clippy::needless_pass_by_ref_mut)]
@@ -380,6 +391,55 @@ impl Stack {
         StackBox::<T>::new_uninit(self, 1)
     }
 
+    /// can a `StackBox<T>` be allocated without overflowing the stack
+    pub(crate) fn can_alloc<T>(&self) -> bool {
+        let free = (self.end() as usize - self.begin() as usize) / std::mem::size_of::<usize>();
+        free >= StackBox::<T>::max_words()
+    }
+
+    /// reserve `size` words from the stack box area as a separate stack, the
+    /// boxes allocated in it never overlap the generator frames
+    /// it's not deallocated by the boxes
+    pub(crate) fn split_off(&mut self, size: usize) -> Stack {
+        let top = self.end();
+        unsafe {
+            *self.get_offset() += size;
+            let area = Stack {
+                buf: SysStack::new(top as *mut c_void, self.end() as *mut c_void),
+            };
+            // init the stack box usage
+            *area.get_offset() = 1;
+            area
+        }
+    }
+
+    /// the part of a stack below `sp` as a separate stack, a suspended
+    /// context keeps its frames above `sp`. the red zone is skipped
+    /// # Safety
+    /// `sp` must be the stack pointer of a suspended context on the stack
+    /// that starts at `bottom`
+    pub(crate) unsafe fn below(sp: usize, bottom: usize) -> Stack {
+        let top = (sp - RED_ZONE) & !15;
+        let area = Stack {
+            buf: SysStack::new(top as *mut c_void, bottom as *mut c_void),
+        };
+        // init the stack box usage
+        *area.get_offset() = 1;
+        area
+    }
+
+    /// get the stack box usage
+    pub(crate) fn offset(&self) -> usize {
+        unsafe { *self.get_offset() }
+    }
+
+    /// reset the stack box usage, all the boxes above the offset are discarded
+    /// # Safety
+    /// the discarded boxes must not be used or dropped after this
+    pub(crate) unsafe fn reset_offset(&mut self, offset: usize) {
+        *self.get_offset() = offset;
+    }
+
     // get offset
     fn get_offset(&self) -> *mut usize {
         unsafe { (self.buf.top as *mut usize).offset(-1) }
@@ -49,11 +49,14 @@ unsafe extern "C" fn signal_handler(
     }
 
     eprintln!(
-        "\ncoroutine in thread '{}' has overflowed its stack\n",
+        "\ncoroutine '{}' in thread '{}' has overflowed its stack\n",
+        ContextStack::current().top().display_name(),
         std::thread::current().name().unwrap_or("<unknown>")
     );
 
-    ContextStack::current().top().err = Some(Box::new(crate::Error::StackErr));
+    let cur = ContextStack::current().top();
+    cur.err = Some(Box::new(crate::Error::StackErr));
+    cur._ref = 3;
 
     let mut sigset: libc::sigset_t = mem::zeroed();
     libc::sigemptyset(&mut sigset);
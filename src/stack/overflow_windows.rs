@@ -17,13 +17,15 @@ unsafe extern "system" fn vectored_handler(exception_info: *mut EXCEPTION_POINTE
         && guard::current().contains(&(context.Rsp as usize))
     {
         eprintln!(
-            "\ncoroutine in thread '{}' has overflowed its stack\n",
+            "\ncoroutine '{}' in thread '{}' has overflowed its stack\n",
+            ContextStack::current().top().display_name(),
             std::thread::current().name().unwrap_or("<unknown>")
         );
 
         let env = ContextStack::current();
         let cur = env.top();
         cur.err = Some(Box::new(crate::Error::StackErr));
+        cur._ref = 3;
 
         context_init(env.pop_context(cur as *mut _), context);
 
@@ -0,0 +1,96 @@
+//! # generator stats
+//!
+//! opt-in per generator counters of the switches and the cpu time
+//!
+//! the stats are recorded for the generators created after `enable` is
+//! called, or for one generator by `Generator::enable_stats`. the cpu time
+//! is the thread cpu time spent inside the generator, it's only measured
+//! on unix by `clock_gettime(CLOCK_THREAD_CPUTIME_ID)`.
+//!
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// start recording the stats of the new generators
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// stop recording the stats of the new generators, the recording ones are not affected
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// is the stats recording enabled for new generators
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// the stats of a generator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GeneratorStats {
+    /// how many times the generator is resumed
+    pub resumes: u64,
+    /// how many times the generator yields back
+    pub yields: u64,
+    /// the thread cpu time spent inside the generator
+    pub cpu_time: Duration,
+}
+
+/// the counters updated by the thread running the generator,
+/// they are shared with the registry entry
+#[derive(Default)]
+pub(crate) struct StatsCell {
+    resumes: AtomicU64,
+    yields: AtomicU64,
+    cpu_ns: AtomicU64,
+}
+
+impl StatsCell {
+    /// the generator is going to run, returns the current thread cpu time
+    #[inline]
+    pub(crate) fn on_resume(&self) -> u64 {
+        // there is only one writer, no need of the atomic add
+        let resumes = self.resumes.load(Ordering::Relaxed);
+        self.resumes.store(resumes + 1, Ordering::Relaxed);
+        thread_cpu_ns()
+    }
+
+    /// the generator is switched back, `start` is returned by `on_resume`
+    #[inline]
+    pub(crate) fn on_switch_back(&self, start: u64, yielded: bool) {
+        let used = thread_cpu_ns().saturating_sub(start);
+        let cpu_ns = self.cpu_ns.load(Ordering::Relaxed);
+        self.cpu_ns.store(cpu_ns + used, Ordering::Relaxed);
+        if yielded {
+            let yields = self.yields.load(Ordering::Relaxed);
+            self.yields.store(yields + 1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn get(&self) -> GeneratorStats {
+        GeneratorStats {
+            resumes: self.resumes.load(Ordering::Relaxed),
+            yields: self.yields.load(Ordering::Relaxed),
+            cpu_time: Duration::from_nanos(self.cpu_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn thread_cpu_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_ns() -> u64 {
+    0
+}
@@ -3,6 +3,7 @@
 //! generator yield implementation
 //!
 use std::any::Any;
+use std::panic::Location;
 use std::sync::atomic;
 
 use crate::gen_impl::{unlikely, Generator};
@@ -11,6 +12,10 @@ use crate::rt::{is_generator, Context, ContextStack, Error};
 
 /// it's a special return instruction that yield nothing
 /// but only terminate the generator safely
+///
+/// with `panic = "abort"` it can't unwind, the frames on the generator stack
+/// are leaked without drop after the defers run, `Gn::new_scoped_done` is
+/// the leak free way
 #[macro_export]
 macro_rules! done {
     () => {{
@@ -20,15 +25,24 @@ macro_rules! done {
 
 /// don't use it directly, use done!() macro instead
 /// would panic if use in none generator context
+///
+/// with `panic = "abort"` the generator can't be finished by a `Done` panic,
+/// it's finished the way `CancelPolicy::Defer` does: the defers run and the
+/// generator locals are dropped, but the frames on its stack are leaked
+/// without drop. use `Gn::new_scoped_done` to return instead
 #[doc(hidden)]
 #[inline]
 pub fn done<T>() -> T {
     assert!(is_generator(), "done is only possible in a generator");
-    std::panic::panic_any(Error::Done)
+    if cfg!(panic = "abort") {
+        crate::detail::exit()
+    }
+    std::panic::resume_unwind(Box::new(Error::Done))
 }
 
 /// switch back to parent context
 #[inline]
+#[track_caller]
 pub fn yield_now() {
     let env = ContextStack::current();
     let cur = env.top();
@@ -36,13 +50,39 @@ pub fn yield_now() {
 }
 
 #[inline]
+#[track_caller]
 pub fn raw_yield_now(env: &ContextStack, cur: &mut Context) {
+    if unlikely(cur.hooked) {
+        on_yield(cur, Location::caller());
+    }
     let parent = env.pop_context(cur as *mut _);
     RegContext::swap(&mut cur.regs, &parent.regs);
 }
 
+/// the yield hooks, see `Context::hooked`
+#[cold]
+#[inline(never)]
+fn on_yield(cur: &mut Context, location: &'static Location<'static>) {
+    // a panic caught inside the generator leaves its site, it's not for the next panic
+    if cur.panic_site.is_some() {
+        cur.panic_site = None;
+        cur.update_hooked();
+    }
+    // the finish of the generator is not a yield
+    if cur._ref & 0x3 != 0 {
+        return;
+    }
+    if let Some(entry) = cur.entry.as_ref() {
+        // the stack grows down from the end
+        let sp = &location as *const _ as usize;
+        let used = cur.stack_guard.1.saturating_sub(sp) / std::mem::size_of::<usize>();
+        entry.on_yield(location, used);
+    }
+}
+
 /// raw yield without catch passed in para
 #[inline]
+#[track_caller]
 fn raw_yield<T: Any>(env: &ContextStack, context: &mut Context, v: T) {
     // check the context
     if unlikely(!context.is_generator()) {
@@ -55,13 +95,14 @@ fn raw_yield<T: Any>(env: &ContextStack, context: &mut Context, v: T) {
 
     // here we just panic to exit the func
     if unlikely(context._ref != 1) {
-        std::panic::panic_any(Error::Cancel);
+        std::panic::resume_unwind(Box::new(Error::Cancel));
     }
 }
 
 /// yield something without catch passed in para
 #[inline]
 #[deprecated(since = "0.6.18", note = "please use `scope` version instead")]
+#[track_caller]
 pub fn yield_with<T: Any>(v: T) {
     let env = ContextStack::current();
     let context = env.top();
@@ -96,6 +137,7 @@ fn raw_get_yield<A: Any>(context: &mut Context) -> Option<A> {
 // since we will come back later
 #[inline]
 #[deprecated(since = "0.6.18", note = "please use `scope` version instead")]
+#[track_caller]
 pub fn yield_<A: Any, T: Any>(v: T) -> Option<A> {
     let env = ContextStack::current();
     let context = env.top();
@@ -106,9 +148,12 @@ pub fn yield_<A: Any, T: Any>(v: T) -> Option<A> {
 
 /// `yield_from`
 #[deprecated(since = "0.6.18", note = "please use `scope` version instead")]
+#[track_caller]
 pub fn yield_from<A: Any, T: Any>(mut g: Generator<A, T>) -> Option<A> {
     let env = ContextStack::current();
     let context = env.top();
+    // the delegate sees the closing flag, see `Scope::is_closing`
+    unsafe { (*g.as_delegate()).delegator = context };
     let mut p = context.get_para();
     while unlikely(!g.is_done()) {
         match g.raw_send(p) {
@@ -135,7 +180,7 @@ pub fn co_yield_with<T: Any>(v: T) {
 
     // here we just panic to exit the func
     if unlikely(context._ref != 1) {
-        std::panic::panic_any(Error::Cancel);
+        std::panic::resume_unwind(Box::new(Error::Cancel));
     }
 
     context.co_set_ret(v);
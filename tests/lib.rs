@@ -278,11 +278,8 @@ fn test_panic_inside() {
             });
             g.resume();
         }) {
-            match panic.downcast_ref::<&str>() {
-                // why can't get the message here?? is it lost?
-                Some(msg) => println!("get panic: {msg:?}"),
-                None => println!("can't get panic message"),
-            }
+            let panic = panic.downcast_ref::<GeneratorPanic>().unwrap();
+            assert_eq!(panic.message(), Some("panic inside!"));
         }
         // wrapper dropped here
     }
@@ -543,3 +540,1591 @@ fn test_yield_float() {
     assert_eq!(i, 23328.0);
     assert!(g.is_done());
 }
+
+#[test]
+fn test_close() {
+    let mut g = Gn::<u32>::new_scoped(|mut s| {
+        let mut sum = 0;
+        loop {
+            match s.yield_(sum) {
+                Some(v) => sum += v,
+                None => {
+                    assert!(s.is_closing());
+                    // flush the final value
+                    return sum * 10;
+                }
+            }
+        }
+    });
+
+    assert_eq!(g.raw_send(None), Some(0));
+    assert_eq!(g.send(1), 1);
+    assert_eq!(g.send(2), 3);
+    assert_eq!(g.close(), Some(30));
+    assert!(g.is_done());
+    assert_eq!(g.close(), None);
+}
+
+#[test]
+fn test_close_delegate() {
+    let mut g = Gn::<u32>::new_scoped(|mut s| {
+        let inner = Gn::<u32>::new_scoped(|mut s| {
+            let mut sum = 0;
+            while let Some(v) = s.yield_(sum) {
+                sum += v;
+            }
+            // the delegate sees the close of the delegator
+            assert!(s.is_closing());
+            sum * 10
+        });
+        s.yield_from(inner);
+        0
+    });
+
+    assert_eq!(g.raw_send(None), Some(0));
+    assert_eq!(g.send(1), 1);
+    assert_eq!(g.close(), Some(10));
+    assert!(g.is_done());
+}
+
+#[test]
+fn test_close_keep_yielding() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let x = Arc::new(AtomicUsize::new(0));
+    let x1 = x.clone();
+    let mut g = Gn::<()>::new_scoped(move |mut s| {
+        let mut i = 0;
+        loop {
+            i += 1;
+            s.yield_(i);
+            x1.store(i, Ordering::SeqCst);
+        }
+    });
+
+    assert_eq!(g.next(), Some(1));
+    // the yielded value is returned and the generator is cancelled
+    assert_eq!(g.close(), Some(2));
+    assert!(g.is_done());
+    assert_eq!(x.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_close_not_started() {
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        s.yield_(1);
+        2
+    });
+
+    assert_eq!(g.close(), None);
+    assert!(g.is_done());
+    assert_eq!(g.next(), None);
+}
+
+#[test]
+fn test_defer() {
+    use std::sync::{Arc, Mutex};
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let l = log.clone();
+    let mut g = Gn::<()>::new_scoped(move |mut s| {
+        let l1 = l.clone();
+        s.defer(move || l1.lock().unwrap().push(1));
+        s.yield_(0);
+        let l2 = l.clone();
+        s.defer(move || l2.lock().unwrap().push(2));
+        s.yield_(0);
+        l.lock().unwrap().push(0);
+        3
+    });
+
+    assert_eq!(g.next(), Some(0));
+    assert_eq!(g.next(), Some(0));
+    assert!(log.lock().unwrap().is_empty());
+    assert_eq!(g.next(), Some(3));
+    assert_eq!(*log.lock().unwrap(), [0, 2, 1]);
+}
+
+#[test]
+fn test_defer_cancel() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let c = count.clone();
+    let mut g = Gn::<()>::new_scoped(move |mut s| {
+        for _ in 0..2 {
+            let c = c.clone();
+            s.defer(move || {
+                c.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        loop {
+            s.yield_(());
+        }
+    });
+
+    g.next();
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    drop(g);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_defer_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let flag = AtomicBool::new(false);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut g = Gn::<()>::new_scoped(|mut s| {
+            s.defer(|| flag.store(true, Ordering::SeqCst));
+            panic!("panic inside!");
+        });
+        g.resume();
+    }));
+
+    assert!(result.is_err());
+    assert!(flag.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_defer_area() {
+    use std::sync::{Arc, Mutex};
+
+    // more than the defer area on the stack holds
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let l = log.clone();
+    let mut g = Gn::<()>::new_scoped(move |mut s| {
+        let id = current_id();
+        for i in 0..64 {
+            let l = l.clone();
+            // run in the generator context
+            s.defer(move || {
+                assert_eq!(current_id(), id);
+                l.lock().unwrap().push(i);
+            });
+        }
+        s.yield_(0);
+        1
+    });
+    unsafe { g.set_cancel_policy(CancelPolicy::Defer) };
+    assert_eq!(g.next(), Some(0));
+    drop(g);
+    let expected: Vec<_> = (0..64).rev().collect();
+    assert_eq!(*log.lock().unwrap(), expected);
+
+    // a panicking defer is not propagated out of the drop
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let l = log.clone();
+    let mut g = Gn::<()>::new_scoped(move |mut s| {
+        s.defer(move || l.lock().unwrap().push(1));
+        s.defer(|| panic!("defer panic"));
+        s.yield_(0);
+        1
+    });
+    g.set_panic_report(PanicReport::Silent);
+    unsafe { g.set_cancel_policy(CancelPolicy::Defer) };
+    assert_eq!(g.next(), Some(0));
+    drop(g);
+    assert_eq!(*log.lock().unwrap(), [1]);
+}
+
+#[test]
+fn test_cancel_policy() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Guard(Arc<AtomicUsize>);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.fetch_add(10, Ordering::SeqCst);
+        }
+    }
+
+    assert_eq!(CancelPolicy::default(), CancelPolicy::Unwind);
+
+    for (policy, expected) in [
+        (CancelPolicy::Unwind, 11),
+        (CancelPolicy::Leak, 0),
+        (CancelPolicy::Defer, 1),
+    ] {
+        let count = Arc::new(AtomicUsize::new(0));
+        let c = count.clone();
+        let mut g = Gn::<()>::new_scoped(move |mut s| {
+            let c1 = c.clone();
+            s.defer(move || {
+                c1.fetch_add(1, Ordering::SeqCst);
+            });
+            let _guard = Guard(c);
+            loop {
+                s.yield_(());
+            }
+        });
+        unsafe { g.set_cancel_policy(policy) };
+        assert_eq!(g.cancel_policy(), policy);
+
+        g.next();
+        g.cancel();
+        assert!(g.is_done());
+        assert_eq!(count.load(Ordering::SeqCst), expected);
+    }
+}
+
+#[test]
+fn test_leak_delegate() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Drops(AtomicUsize);
+    impl GeneratorObserver for Drops {
+        fn on_drop(&self, _id: GeneratorId, _lifetime: std::time::Duration) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    for policy in [CancelPolicy::Leak, CancelPolicy::Defer] {
+        let drops = Arc::new(Drops::default());
+        let d = drops.clone();
+        let mut g = Gn::<()>::new_scoped(move |mut s| {
+            let mut inner = Gn::<()>::new_scoped(|mut s| loop {
+                s.yield_(1);
+            });
+            inner.set_observer(Some(d));
+            s.yield_from(inner);
+            0
+        });
+        unsafe { g.set_cancel_policy(policy) };
+        assert_eq!(g.next(), Some(1));
+        g.cancel();
+        // the leaked delegate is released
+        assert_eq!(drops.0.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[test]
+fn test_re_init_after_leak() {
+    let clo = || {
+        |mut s: Scope<'_, 'static, (), _>| {
+            s.yield_(0);
+            s.yield_(3);
+            5
+        }
+    };
+
+    let mut g = Gn::new_opt(0x800, || 0);
+    unsafe { g.set_cancel_policy(CancelPolicy::Leak) };
+    let (_, used) = g.stack_usage();
+
+    for _ in 0..10 {
+        g.scoped_init(clo());
+        assert_eq!(g.next(), Some(0));
+        g.cancel();
+        assert!(g.is_done());
+    }
+
+    g.scoped_init(clo());
+    assert_eq!(g.next(), Some(0));
+    assert_eq!(g.next(), Some(3));
+    assert_eq!(g.next(), Some(5));
+    assert!(g.is_done());
+    assert_eq!(g.stack_usage().1, used);
+}
+
+#[test]
+fn test_scoped_done() {
+    let mut g = Gn::<()>::new_scoped_done(|mut s| {
+        s.yield_(1);
+        s.yield_(2);
+        None
+    });
+
+    assert_eq!(g.next(), Some(1));
+    assert_eq!(g.next(), Some(2));
+    assert!(!g.is_done());
+    assert_eq!(g.next(), None);
+    assert!(g.is_done());
+
+    let g = Gn::<()>::new_scoped_done_local(|mut s| {
+        s.yield_with(1);
+        Some(2)
+    });
+    assert_eq!(g.collect::<Vec<_>>(), [1, 2]);
+}
+
+#[test]
+fn test_generator_scope() {
+    let mut data = vec![1, 2, 3];
+    let total = generator::scope(|s| {
+        let mut g = s.spawn(|mut sc| {
+            let mut total = 0;
+            for x in data.iter_mut() {
+                *x += 1;
+                total += sc.yield_(*x).unwrap_or(0);
+            }
+            total
+        });
+
+        assert_eq!(g.raw_send(None), Some(2));
+        assert_eq!(g.send(10), 3);
+        assert_eq!(g.send(20), 4);
+        g.send(30)
+    });
+
+    assert_eq!(total, 60);
+    assert_eq!(data, [2, 3, 4]);
+}
+
+#[test]
+fn test_generator_scope_cancel() {
+    struct Guard<'a>(&'a mut u32);
+    impl Drop for Guard<'_> {
+        fn drop(&mut self) {
+            *self.0 = 100;
+        }
+    }
+
+    let mut x = 0;
+    let mut y = 0;
+    generator::scope(|s| {
+        let mut g = s.spawn(|mut sc| {
+            let _guard = Guard(&mut x);
+            loop {
+                sc.yield_(());
+            }
+        });
+        g.next();
+
+        let mut g1 = s.spawn(|mut sc| {
+            let _guard = Guard(&mut y);
+            sc.yield_(());
+        });
+        g1.next();
+        // the handle is forgotten, the scope still cancels it
+        std::mem::forget(g1);
+    });
+
+    assert_eq!(x, 100);
+    assert_eq!(y, 100);
+}
+
+#[test]
+fn test_generator_scope_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut x = 0;
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        generator::scope(|s| {
+            let mut g = s.spawn(|mut sc| {
+                x = 1;
+                sc.defer(|| {});
+                loop {
+                    sc.yield_(());
+                }
+            });
+            g.next();
+            std::mem::forget(g);
+            panic!("panic in scope");
+        })
+    }));
+
+    assert!(r.is_err());
+    assert_eq!(x, 1);
+}
+
+#[test]
+fn test_scope_yield_from_ret() {
+    fn parse_num() -> Generator<'static, char, u32> {
+        Gn::new_scoped(|mut s| {
+            let mut n = 0;
+            let mut c: Option<char> = s.get_yield();
+            loop {
+                if let Some(c) = c {
+                    match c.to_digit(10) {
+                        Some(d) => n = n * 10 + d,
+                        None => return n,
+                    }
+                }
+                c = s.yield_(0);
+            }
+        })
+    }
+
+    let mut g = Gn::new_scoped(|mut s| {
+        let a = s.yield_from_ret(parse_num()).unwrap();
+        let b = s.yield_from_ret(parse_num()).unwrap();
+        a + b
+    });
+
+    let mut out = vec![];
+    for c in "12,30,".chars() {
+        out.push(g.send(c));
+    }
+    assert_eq!(out, [0, 0, 0, 0, 0, 42]);
+    assert!(g.is_done());
+
+    // the sub generator finished by `done!()` has no value
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        let g1 = Gn::new_scoped(|mut s| {
+            s.yield_(1);
+            done!();
+        });
+        assert_eq!(s.yield_from_ret(g1), None);
+        2
+    });
+    assert_eq!(g.next(), Some(1));
+    assert_eq!(g.next(), Some(2));
+    assert!(g.is_done());
+}
+
+#[test]
+fn test_scope_yield_from_map_send() {
+    let mut g = Gn::new_scoped(|mut s| {
+        let g1 = Gn::new_scoped(|mut s| {
+            let mut i: u32 = s.yield_(1u32).unwrap();
+            i = s.yield_(i * 2).unwrap();
+            i * 2
+        });
+
+        let i: u64 = s
+            .yield_from_map(g1, |v| v.to_string(), |v: u64| v as u32)
+            .unwrap();
+        (i * 2).to_string()
+    });
+
+    let n = g.send(3);
+    assert_eq!(n, "1");
+    let n = g.send(4);
+    assert_eq!(n, "8");
+    let n = g.send(10);
+    assert_eq!(n, "20");
+    // the last send has no meaning for the return
+    let n = g.send(7);
+    assert_eq!(n, "14");
+    assert!(g.is_done());
+}
+
+#[test]
+fn test_scope_yield_all() {
+    let mut g = Gn::new_scoped(|mut s| {
+        let i = s.yield_all(vec![1, 2, 3]).unwrap();
+        // the para is already taken, nothing left for an empty iterator
+        assert_eq!(s.yield_all(None), None);
+        i * 2
+    });
+
+    assert_eq!(g.raw_send(None), Some(1));
+    assert_eq!(g.send(4), 2);
+    assert_eq!(g.send(5), 3);
+    assert_eq!(g.send(6), 12);
+    assert!(g.is_done());
+}
+
+#[test]
+fn test_scope_yield_from_deep() {
+    fn chain(depth: usize) -> Generator<'static, usize, usize> {
+        Gn::new_scoped(move |mut s| {
+            if depth == 0 {
+                let mut v = s.get_yield().unwrap();
+                for _ in 0..3 {
+                    v = s.yield_(v + 1).unwrap();
+                }
+                return v * 10;
+            }
+            let v = s.yield_from(chain(depth - 1)).unwrap();
+            v + depth
+        })
+    }
+
+    let mut g = chain(20);
+    assert_eq!(g.send(1), 2);
+    assert_eq!(g.send(5), 6);
+    assert_eq!(g.send(7), 8);
+    // the inner most returns
+    assert_eq!(g.send(2), 20);
+    // then each level returns with the new para
+    assert_eq!(g.send(3), 4);
+    assert_eq!(g.send(4), 6);
+    for _ in 3..20 {
+        g.send(0);
+    }
+    assert_eq!(g.send(100), 120);
+    assert!(g.is_done());
+}
+
+#[test]
+fn test_scope_yield_from_tree() {
+    fn walk(depth: usize, node: usize) -> Generator<'static, (), usize> {
+        Gn::new_scoped(move |mut s| {
+            if depth > 0 {
+                s.yield_from(walk(depth - 1, node * 2));
+                s.yield_from(walk(depth - 1, node * 2 + 1));
+            }
+            node
+        })
+    }
+
+    let v: Vec<usize> = walk(2, 1).collect();
+    assert_eq!(v, [4, 5, 2, 6, 7, 3, 1]);
+}
+
+#[test]
+fn test_scope_yield_from_deep_panic() {
+    fn chain(depth: usize) -> Generator<'static, (), usize> {
+        Gn::new_scoped(move |mut s| {
+            if depth == 0 {
+                s.yield_(0);
+                panic!("inner panic");
+            }
+            if depth == 5 {
+                // the panic of the delegate is caught in the middle
+                let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    s.yield_from(chain(depth - 1));
+                }));
+                assert!(r.is_err());
+                s.yield_(100);
+                return depth;
+            }
+            s.yield_from(chain(depth - 1));
+            depth
+        })
+    }
+
+    let g = chain(10);
+    let v: Vec<usize> = g.collect();
+    assert_eq!(v, [0, 100, 5, 6, 7, 8, 9, 10]);
+}
+
+#[test]
+fn test_scope_yield_from_deep_cancel() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Guard(Arc<AtomicUsize>);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn chain(depth: usize) -> Generator<'static, (), usize> {
+        Gn::new_scoped(move |mut s| {
+            if depth == 0 {
+                loop {
+                    s.yield_(0);
+                }
+            }
+            s.yield_from(chain(depth - 1));
+            depth
+        })
+    }
+
+    let cnt = Arc::new(AtomicUsize::new(0));
+    let guard = Guard(cnt.clone());
+    let mut g = Gn::new_scoped(move |mut s| {
+        let _guard = guard;
+        s.yield_from(chain(10));
+        0
+    });
+    for _ in 0..5 {
+        assert_eq!(g.resume(), Some(0));
+    }
+    // the outer generator is unwound while delegating
+    g.cancel();
+    assert!(g.is_done());
+    assert_eq!(g.resume(), None);
+    assert_eq!(cnt.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_lending() {
+    let mut g = LendingGenerator::<[u8]>::new(|mut l| {
+        let mut buf = Vec::new();
+        for word in "a,bc,def".split(',') {
+            buf.clear();
+            buf.extend_from_slice(word.as_bytes());
+            l.lend(&mut buf);
+            // the change of the consumer is seen by the generator
+            assert_eq!(buf, word.to_uppercase().as_bytes());
+        }
+    });
+
+    let mut v = Vec::new();
+    while let Some(s) = g.next() {
+        s.make_ascii_uppercase();
+        v.push(s.to_vec());
+    }
+    assert!(g.is_done());
+    assert_eq!(v, [&b"A"[..], b"BC", b"DEF"]);
+}
+
+#[test]
+fn test_lending_cancel() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let cnt = Arc::new(AtomicUsize::new(0));
+    let cnt1 = cnt.clone();
+    let mut g = LendingGenerator::<usize>::new(move |mut l| {
+        let mut i = 0;
+        loop {
+            l.lend(&mut i);
+            i += 1;
+            cnt1.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    assert_eq!(g.next(), Some(&mut 0));
+    *g.next().unwrap() += 10;
+    assert_eq!(g.next(), Some(&mut 12));
+    g.cancel();
+    assert!(g.is_done());
+    assert_eq!(g.next(), None);
+    assert_eq!(cnt.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn test_combinator_map() {
+    let g = Gn::<u32>::new_scoped(|mut s| {
+        let mut v = s.get_yield().unwrap();
+        for _ in 0..3 {
+            v = s.yield_(v * 2).unwrap();
+        }
+        v
+    });
+
+    let mut g = g
+        .map_send(|v: &str| v.len() as u32)
+        .map_yield(|v| v.to_string());
+    assert_eq!(g.send("a"), "2");
+    assert_eq!(g.send("ab"), "4");
+    assert_eq!(g.send("abc"), "6");
+    // the return value
+    assert_eq!(g.send("abcd"), "4");
+    assert!(g.is_done());
+}
+
+#[test]
+fn test_combinator_filter_take_until() {
+    let g = Gn::<()>::new_scoped(|mut s| {
+        for i in 0..10 {
+            s.yield_(i);
+        }
+        10
+    });
+    let v: Vec<_> = g.filter(|v| v % 3 == 0).collect();
+    assert_eq!(v, [0, 3, 6, 9]);
+
+    let g = Gn::<()>::new_scoped(|mut s| {
+        for i in 0.. {
+            s.yield_(i);
+        }
+        unreachable!()
+    });
+    let v: Vec<_> = g.take_until(|v| *v == 4).collect();
+    assert_eq!(v, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn test_combinator_chain() {
+    fn counter(n: u32) -> Generator<'static, u32, u32> {
+        Gn::new_scoped(move |mut s| {
+            let mut acc = s.get_yield().unwrap_or(0);
+            for _ in 0..n {
+                acc += s.yield_(acc).unwrap_or(0);
+            }
+            acc
+        })
+    }
+
+    let mut g = counter(1).chain(counter(1));
+    assert_eq!(g.send(1), 1);
+    assert_eq!(g.send(2), 3);
+    // the para goes to the next generator
+    assert_eq!(g.send(10), 10);
+    assert_eq!(g.send(5), 15);
+    assert!(g.is_done());
+    assert_eq!(g.raw_send(Some(1)), None);
+}
+
+#[test]
+fn test_combinator_then_zip_feed() {
+    // running sum of the received values
+    let sum = Gn::<u32>::new_scoped_done(|mut s| {
+        let mut acc = 0;
+        let mut v = s.get_yield();
+        while let Some(i) = v {
+            acc += i;
+            v = s.yield_(acc);
+        }
+        None
+    });
+
+    let square = Gn::<u32>::new_scoped_done(|mut s| {
+        let mut v = s.get_yield();
+        while let Some(i) = v {
+            v = s.yield_(i * i);
+        }
+        None
+    });
+
+    let v: Vec<_> = square.then(sum).zip_feed(1..5).collect();
+    assert_eq!(v, [1, 5, 14, 30]);
+}
+
+#[test]
+fn test_combinator_peekable() {
+    let g = Gn::<u32>::new_scoped(|mut s| {
+        let mut v = s.get_yield().unwrap_or(0);
+        for _ in 0..2 {
+            v = s.yield_(v + 1).unwrap_or(0);
+        }
+        v
+    });
+
+    let mut g = g.peekable();
+    g.set_para(1);
+    assert_eq!(g.peek(), Some(&2));
+    assert_eq!(g.peek(), Some(&2));
+    // the para is kept for the next resume
+    assert_eq!(g.send(5), 2);
+    assert_eq!(g.peek(), Some(&6));
+    assert_eq!(g.send(7), 6);
+    assert!(!g.is_done());
+    assert_eq!(g.send(9), 9);
+    assert!(g.is_done());
+    assert_eq!(g.peek(), None);
+}
+
+#[test]
+fn test_sink() {
+    let mut sink = Sink::new(|mut rx| {
+        let mut v = Vec::new();
+        while let Some(i) = rx.recv() {
+            v.push(i);
+        }
+        v
+    });
+    assert_eq!(sink.push(1), Ok(()));
+    assert_eq!(sink.push(2), Ok(()));
+    assert_eq!(sink.close(), Some(vec![1, 2]));
+    assert!(sink.is_done());
+    assert_eq!(sink.push(3), Err(3));
+    assert_eq!(sink.close(), None);
+
+    // closing a sink that never receives anything
+    let mut sink = Sink::<u32, _>::new(|mut rx| rx.recv().is_none());
+    assert_eq!(sink.close(), Some(true));
+}
+
+#[test]
+fn test_pipeline_stage() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let pushed = Arc::new(AtomicUsize::new(0));
+    let cnt = pushed.clone();
+    // batch the values in pairs, flush the rest when closed
+    let mut sink = pipeline::<u32, _>()
+        .map(|v| v + 1)
+        .stage(|rx, tx| {
+            let mut batch = Vec::new();
+            while let Some(v) = rx.recv() {
+                batch.push(v);
+                if batch.len() == 2 && tx.push(std::mem::take(&mut batch)).is_err() {
+                    return;
+                }
+            }
+            if !batch.is_empty() {
+                let _ = tx.push(batch);
+            }
+        })
+        .filter(move |b| {
+            cnt.fetch_add(1, Ordering::Relaxed);
+            b[0] != 3
+        })
+        .into_sink(Sink::new(|mut rx| {
+            let mut v = Vec::new();
+            while let Some(b) = rx.recv() {
+                v.push(b);
+            }
+            v
+        }));
+
+    for i in 0..5 {
+        sink.push(i).unwrap();
+        // the value is consumed by the downstream before the push returns
+        assert_eq!(pushed.load(Ordering::Relaxed), [0, 1, 1, 2, 2][i as usize]);
+    }
+    assert_eq!(sink.close(), Some(vec![vec![1, 2], vec![5]]));
+}
+
+#[test]
+fn test_pipeline_early_finish() {
+    let mut sink = pipeline::<u32, _>()
+        .map(|v| v * 2)
+        .into_sink(Sink::new(|mut rx| {
+            // only take two values
+            rx.recv().unwrap() + rx.recv().unwrap()
+        }));
+
+    assert_eq!(sink.push(1), Ok(()));
+    assert_eq!(sink.push(2), Ok(()));
+    // the finish of the downstream is propagated to the upstream
+    assert!(sink.is_done());
+    assert_eq!(sink.push(3), Err(3));
+    assert_eq!(sink.close(), Some(6));
+}
+
+#[test]
+fn test_pipeline_broadcast_merge() {
+    let collect = || {
+        Sink::new(|mut rx| {
+            let mut v = Vec::new();
+            while let Some(i) = rx.recv() {
+                v.push(i);
+            }
+            v
+        })
+    };
+
+    let merge = collect().merge();
+    let mut a = pipeline::<u32, _>()
+        .map(|v| v * 10)
+        .into_sink(merge.input());
+    let mut b = merge.input();
+
+    let mut sink = pipeline::<u32, _>().broadcast(vec![
+        collect(),
+        Sink::new(|mut rx| vec![rx.recv().unwrap()]),
+    ]);
+    for i in 1..4 {
+        sink.push(i).unwrap();
+        a.push(i).unwrap();
+        b.push(i).unwrap();
+    }
+    assert_eq!(sink.close(), Some(vec![Some(vec![1, 2, 3]), Some(vec![1])]));
+
+    a.close();
+    b.close();
+    assert_eq!(merge.close(), Some(vec![10, 1, 20, 2, 30, 3]));
+}
+
+#[test]
+fn test_generator_local() {
+    use std::cell::Cell;
+
+    generator_local! {
+        static COUNT: Cell<u32> = Cell::new(0);
+        static NAME: String = String::from("gen");
+    }
+
+    fn counter() -> Generator<'static, (), u32> {
+        Gn::new_scoped(|mut s| {
+            for _ in 0..3 {
+                COUNT.with(|c| c.set(c.get() + 1));
+                s.yield_(COUNT.with(|c| c.get()));
+            }
+            NAME.with(|n| n.len() as u32)
+        })
+    }
+
+    // each generator has its own value
+    let mut a = counter();
+    let mut b = counter();
+    assert_eq!(a.resume(), Some(1));
+    assert_eq!(b.resume(), Some(1));
+    assert_eq!(a.resume(), Some(2));
+    // the nested one doesn't see the parent's value
+    let mut g = Gn::<()>::new_scoped(move |mut s| {
+        COUNT.with(|c| c.set(10));
+        s.yield_(b.resume().unwrap());
+        COUNT.with(|c| c.get())
+    });
+    assert_eq!(g.resume(), Some(2));
+    assert_eq!(g.resume(), Some(10));
+    assert_eq!(a.resume(), Some(3));
+    assert_eq!(a.resume(), Some(3));
+    assert!(a.is_done());
+
+    assert_eq!(COUNT.try_with(|c| c.get()), Err(AccessError));
+}
+
+#[test]
+fn test_generator_local_drop() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    generator_local! {
+        static GUARD: Guard = Guard;
+    }
+
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        GUARD.with(|_| ());
+        s.yield_(());
+        GUARD.with(|_| ());
+    });
+    g.resume();
+    assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+    g.resume();
+    // dropped when the generator is finished
+    assert!(g.is_done());
+    assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+    // dropped when the generator is cancelled
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        GUARD.with(|_| ());
+        loop {
+            s.yield_(());
+        }
+    });
+    g.resume();
+    drop(g);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn test_generator_local_discard() {
+    use std::sync::Mutex;
+
+    // the id of the generator that the local is dropped in
+    static DROPPED_IN: Mutex<Vec<Option<GeneratorId>>> = Mutex::new(Vec::new());
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DROPPED_IN.lock().unwrap().push(current_id());
+        }
+    }
+
+    generator_local! {
+        static GUARD: Guard = Guard;
+    }
+
+    // the discarded generator drops its locals in its own context
+    for policy in [CancelPolicy::Leak, CancelPolicy::Defer] {
+        let mut g = Gn::<()>::new_scoped(|mut s| {
+            GUARD.with(|_| ());
+            loop {
+                s.yield_(());
+            }
+        });
+        unsafe { g.set_cancel_policy(policy) };
+        let id = g.id();
+        g.resume();
+        drop(g);
+        assert_eq!(DROPPED_IN.lock().unwrap().pop(), Some(Some(id)));
+    }
+}
+
+#[test]
+fn test_generator_state() {
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        let id = current_id().unwrap();
+        s.yield_(id);
+        id
+    });
+    let id = g.id();
+    assert_eq!(g.state(), GeneratorState::Created);
+    assert_eq!(g.resume(), Some(id));
+    assert_eq!(g.state(), GeneratorState::Suspended);
+    assert_eq!(g.resume(), Some(id));
+    assert_eq!(g.state(), GeneratorState::Completed);
+    assert_eq!(current_id(), None);
+
+    let mut g1 = Gn::<()>::new_scoped(|mut s| {
+        s.yield_(0);
+        panic!("panic in generator");
+    });
+    assert_ne!(g1.id(), id);
+    g1.set_name("panic");
+    assert_eq!(g1.name(), Some("panic"));
+    g1.resume();
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| g1.resume())).is_err());
+    assert_eq!(g1.state(), GeneratorState::Panicked);
+
+    let mut g2 = Gn::<()>::new_scoped(|mut s| loop {
+        s.yield_(0);
+    });
+    g2.resume();
+    g2.cancel();
+    assert_eq!(g2.state(), GeneratorState::Cancelled);
+    let mut g3 = Gn::<()>::new_scoped(|_| 0);
+    g3.cancel();
+    assert_eq!(g3.state(), GeneratorState::Cancelled);
+}
+
+#[test]
+fn test_generator_debug() {
+    fn chain(depth: usize) -> Generator<'static, (), usize> {
+        Gn::new_scoped(move |mut s| {
+            if depth == 0 {
+                loop {
+                    s.yield_(0);
+                }
+            }
+            s.yield_from(chain(depth - 1));
+            depth
+        })
+    }
+
+    let mut g = chain(3);
+    g.set_name("chain");
+    g.resume();
+    g.resume();
+    let s = format!("{g:?}");
+    assert!(s.contains(&format!("id: {}", g.id())));
+    assert!(s.contains("name: Some(\"chain\")"));
+    assert!(s.contains("state: Suspended"));
+    assert!(s.contains("depth: 3"));
+}
+
+#[test]
+#[cfg(all(
+    unix,
+    any(
+        all(target_arch = "x86_64", not(target_os = "macos")),
+        all(target_arch = "aarch64", not(target_vendor = "apple"))
+    )
+))]
+fn test_generator_backtrace() {
+    use std::backtrace::BacktraceStatus;
+
+    #[inline(never)]
+    fn parked_here(s: &mut Scope<'_, 'static, (), i32>) -> i32 {
+        s.yield_(1);
+        2
+    }
+
+    let mut g = Gn::<()>::new_scoped(|mut s| parked_here(&mut s));
+    assert!(g.backtrace().is_none());
+    assert_eq!(g.resume(), Some(1));
+
+    let bt = g.backtrace().unwrap();
+    if bt.status() == BacktraceStatus::Captured {
+        assert!(bt.to_string().contains("parked_here"));
+    }
+    // the generator is not moved on by the backtrace
+    assert_eq!(g.state(), GeneratorState::Suspended);
+    assert!(g.backtrace().is_some());
+    assert_eq!(g.resume(), Some(2));
+    assert!(g.backtrace().is_none());
+
+    // the capture is not counted as a resume
+    let mut g = Gn::<()>::new_scoped(|mut s| parked_here(&mut s));
+    g.enable_stats();
+    g.resume();
+    assert!(g.backtrace().is_some());
+    assert_eq!(g.stats().unwrap().resumes, 1);
+
+    // not enough stack left to walk it
+    let mut g = Gn::<()>::new_scoped_opt(0x400, |mut s| parked_here(&mut s));
+    assert_eq!(g.resume(), Some(1));
+    assert!(g.backtrace().is_none());
+    assert_eq!(g.resume(), Some(2));
+}
+
+#[test]
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn test_backtrace_stitched() {
+    use std::backtrace::{Backtrace, BacktraceStatus};
+
+    #[inline(never)]
+    fn resume_here(g: &mut Generator<'static, (), Backtrace>) -> Option<Backtrace> {
+        g.resume()
+    }
+
+    let mut g = Gn::<()>::new_scoped(|_| Backtrace::force_capture());
+    let bt = resume_here(&mut g).unwrap();
+    assert_eq!(bt.status(), BacktraceStatus::Captured);
+    // the frames go on from the generator into the resumer
+    let bt = bt.to_string();
+    assert!(bt.contains("gen_init"));
+    assert!(bt.contains("resume_here"));
+    assert!(bt.contains("test_backtrace_stitched"));
+}
+
+#[test]
+fn test_sched_executor() {
+    use generator::sched::{self, Executor};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let exec = Executor::new();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    for name in ["a", "b"] {
+        let log = log.clone();
+        exec.spawn(move || {
+            for i in 0..3 {
+                log.borrow_mut().push(format!("{name}{i}"));
+                sched::yield_now();
+            }
+        });
+    }
+    // spawned from a task and joined in a task
+    let e = exec.clone();
+    let h = exec.spawn(move || {
+        let h = e.spawn(|| 10);
+        h.join().unwrap() + 1
+    });
+    assert_eq!(exec.tasks(), 3);
+    exec.run_until_idle();
+    assert!(exec.is_idle());
+    assert_eq!(*log.borrow(), ["a0", "b0", "a1", "b1", "a2", "b2"]);
+    assert!(h.is_finished());
+    assert_eq!(h.join().unwrap(), 11);
+
+    // join outside runs the executor
+    let h = exec.spawn(|| -> i32 { panic!("task panic") });
+    let err = h.join().unwrap_err();
+    let p = err.downcast_ref::<GeneratorPanic>().unwrap();
+    assert_eq!(p.message(), Some("task panic"));
+
+    assert!(!sched::in_task());
+    assert!(std::panic::catch_unwind(sched::yield_now).is_err());
+
+    // joined in a task of another executor that's not running
+    let other = Executor::new();
+    let inner = other.spawn(|| {
+        sched::yield_now();
+        20
+    });
+    let h = exec.spawn(move || inner.join().unwrap() + 1);
+    assert_eq!(h.join().unwrap(), 21);
+    assert!(other.is_idle());
+
+    // the unfinished tasks are cancelled with the executor
+    let h = exec.spawn(|| loop {
+        sched::yield_now();
+    });
+    drop(exec);
+    let err = h.join().unwrap_err();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Cancel)));
+}
+
+#[test]
+fn test_sched_spawn_generator() {
+    use generator::sched::{self, Executor};
+    use std::sync::{Arc, Mutex};
+
+    let exec = Executor::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let l = log.clone();
+    let a = exec.spawn_generator(Gn::<()>::new_scoped(move |mut s| {
+        for i in 0..3 {
+            l.lock().unwrap().push(i);
+            s.yield_(());
+        }
+    }));
+    let l = log.clone();
+    let b = exec.spawn(move || {
+        for i in 10..13 {
+            l.lock().unwrap().push(i);
+            sched::yield_now();
+        }
+    });
+    exec.run_until_idle();
+    assert!(a.join().is_ok());
+    assert!(b.join().is_ok());
+    // the yields of the generator switch to the other task
+    assert_eq!(*log.lock().unwrap(), [0, 10, 1, 11, 2, 12]);
+
+    let h = exec.spawn_generator(Gn::<()>::new_scoped(|_| panic!("generator task")));
+    let err = h.join().unwrap_err();
+    let p = err.downcast_ref::<GeneratorPanic>().unwrap();
+    assert_eq!(p.message(), Some("generator task"));
+}
+
+// the task is moved between the workers while it parks, run it with
+// `cargo test --release` to check the thread locals are not cached
+#[test]
+fn test_sched_migrate() {
+    use generator::sched::{self, channel, Runtime};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    let rt = Runtime::new(4).unwrap();
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let (tx, rx) = channel::bounded(1);
+    let rx = Arc::new(rx);
+    let receivers: Vec<_> = (0..4)
+        .map(|_| {
+            let rx = rx.clone();
+            let threads = threads.clone();
+            rt.spawn(move || {
+                let mut n = 0;
+                while let Ok(v) = rx.recv() {
+                    assert!(sched::in_task());
+                    threads.lock().unwrap().insert(std::thread::current().id());
+                    n += v;
+                    sched::yield_now();
+                }
+                n
+            })
+        })
+        .collect();
+    drop(rx);
+    let sender = rt.spawn(move || {
+        for _ in 0..2000 {
+            tx.send(1).unwrap();
+            assert!(sched::in_task());
+        }
+    });
+    sender.join().unwrap();
+    let total: i32 = receivers.into_iter().map(|h| h.join().unwrap()).sum();
+    assert_eq!(total, 2000);
+    assert!(!threads.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_sched_runtime() {
+    use generator::sched::{self, Runtime};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    let rt = Runtime::new(4).unwrap();
+    assert_eq!(rt.workers(), 4);
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let handles: Vec<_> = (0..32)
+        .map(|i| {
+            let threads = threads.clone();
+            rt.spawn(move || {
+                let mut sum = 0;
+                for j in 0..10 {
+                    threads.lock().unwrap().insert(std::thread::current().id());
+                    sum += j;
+                    sched::yield_now();
+                }
+                i + sum
+            })
+        })
+        .collect();
+    for (i, h) in handles.into_iter().enumerate() {
+        assert_eq!(h.join().unwrap(), i + 45);
+    }
+    assert!(!threads.lock().unwrap().is_empty());
+    assert!(!threads
+        .lock()
+        .unwrap()
+        .contains(&std::thread::current().id()));
+
+    // spawned from a task and joined in a task
+    let handle = rt.handle();
+    let h = rt.spawn(move || {
+        let hs: Vec<_> = (0..8).map(|i| handle.spawn(move || i * 2)).collect();
+        hs.into_iter().map(|h| h.join().unwrap()).sum::<i32>()
+    });
+    assert_eq!(h.join().unwrap(), 56);
+
+    let h = rt.spawn(|| -> i32 { panic!("task panic") });
+    let err = h.join().unwrap_err();
+    let p = err.downcast_ref::<GeneratorPanic>().unwrap();
+    assert_eq!(p.message(), Some("task panic"));
+
+    // the unfinished tasks are cancelled with the runtime
+    let (tx, rx) = std::sync::mpsc::channel();
+    let h = rt.spawn(move || {
+        tx.send(()).unwrap();
+        loop {
+            sched::yield_now();
+        }
+    });
+    rx.recv().unwrap();
+    let handle = rt.handle();
+    drop(rt);
+    let err = h.join().unwrap_err();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Cancel)));
+    assert_eq!(handle.tasks(), 0);
+    // spawned after the shutdown
+    let err = handle.spawn(|| 1).join().unwrap_err();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Cancel)));
+}
+
+#[test]
+fn test_sched_channel() {
+    use generator::sched::channel::{self, TryRecvError, TrySendError};
+    use generator::sched::{self, Executor, Runtime};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // the sender parks on the full channel and the receiver unparks it
+    let exec = Executor::new();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let (tx, rx) = channel::bounded(2);
+    let l = log.clone();
+    exec.spawn(move || {
+        for i in 0..5 {
+            tx.send(i).unwrap();
+            l.borrow_mut().push(format!("send {i}"));
+        }
+    });
+    let l = log.clone();
+    exec.spawn(move || {
+        for v in &rx {
+            l.borrow_mut().push(format!("recv {v}"));
+        }
+    });
+    exec.run_until_idle();
+    let log = log.borrow();
+    assert_eq!(log.len(), 10);
+    assert_eq!(log[..3], ["send 0", "send 1", "recv 0"]);
+
+    // try and close
+    let (tx, rx) = channel::bounded(1);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    tx.try_send(1).unwrap();
+    assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+    assert_eq!(rx.len(), 1);
+    rx.close();
+    assert!(tx.is_closed());
+    assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+    assert_eq!(tx.send(4).unwrap_err().0, 4);
+    // the left value is still received
+    assert_eq!(rx.recv(), Ok(1));
+    assert!(rx.recv().is_err());
+    let (tx, rx) = channel::unbounded::<i32>();
+    drop(tx);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+    // unparked from another thread
+    let exec = Executor::new();
+    let (tx, rx) = channel::unbounded();
+    let h = exec.spawn(move || rx.recv().unwrap() + 1);
+    let t = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        tx.send(1).unwrap();
+    });
+    assert_eq!(h.join().unwrap(), 2);
+    t.join().unwrap();
+
+    let exec = Executor::new();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let h = exec.spawn(move || {
+        tx.send(sched::unparker()).unwrap();
+        sched::park();
+        1
+    });
+    exec.spawn(move || rx.recv().unwrap().unpark());
+    exec.run_until_idle();
+    assert_eq!(h.join().unwrap(), 1);
+
+    // mpmc across the workers
+    let rt = Runtime::new(4).unwrap();
+    let (tx, rx) = channel::bounded(4);
+    let (done_tx, done_rx) = channel::unbounded();
+    for p in 0..4 {
+        let tx = tx.clone();
+        rt.spawn(move || {
+            for i in 0..100 {
+                tx.send(p * 100 + i).unwrap();
+            }
+        });
+    }
+    drop(tx);
+    for _ in 0..3 {
+        let rx = rx.clone();
+        let done_tx = done_tx.clone();
+        rt.spawn(move || {
+            let sum = rx.iter().sum::<i32>();
+            done_tx.send(sum).unwrap();
+        });
+    }
+    drop(rx);
+    drop(done_tx);
+    // received out of the tasks
+    assert_eq!(done_rx.iter().sum::<i32>(), (0..400).sum::<i32>());
+
+    // the parked task is cancelled with the runtime
+    let (tx, rx) = channel::unbounded::<()>();
+    let h = rt.spawn(move || rx.recv());
+    drop(rt);
+    let err = h.join().unwrap_err();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Cancel)));
+    drop(tx);
+
+    // the cancelled receiver doesn't take the wake up of the next one
+    let exec = Executor::new();
+    let (tx, rx) = channel::bounded::<i32>(1);
+    let rx2 = rx.clone();
+    exec.spawn(move || rx2.recv());
+    exec.spawn(|| ()).join().unwrap();
+    drop(exec);
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || done_tx.send(rx.recv()));
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    tx.send(1).unwrap();
+    let r = done_rx.recv_timeout(std::time::Duration::from_secs(10));
+    assert_eq!(r.unwrap(), Ok(1));
+}
+
+#[test]
+fn test_sched_sync() {
+    use generator::sched::sync::{Barrier, Condvar, Mutex, Notify, RwLock, Semaphore};
+    use generator::sched::{self, Executor, Runtime};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // the waiters get the lock in FIFO order
+    let exec = Executor::new();
+    let lock = Rc::new(Mutex::new(Vec::new()));
+    for i in 0..4 {
+        let lock = lock.clone();
+        exec.spawn(move || {
+            let mut v = lock.lock();
+            v.push(i);
+            sched::yield_now();
+            sched::yield_now();
+        });
+    }
+    exec.run_until_idle();
+    assert_eq!(*lock.lock(), [0, 1, 2, 3]);
+    assert!(lock.try_lock().is_some());
+
+    // the waiting writer blocks the readers after it
+    let exec = Executor::new();
+    let lock = Rc::new(RwLock::new(0));
+    let log = Rc::new(RefCell::new(Vec::new()));
+    for (name, write) in [("r1", false), ("w", true), ("r2", false)] {
+        let (lock, log) = (lock.clone(), log.clone());
+        exec.spawn(move || {
+            if write {
+                *lock.write() += 1;
+            } else {
+                let v = lock.read();
+                sched::yield_now();
+                log.borrow_mut().push(format!("{name} {}", *v));
+            }
+        });
+    }
+    exec.run_until_idle();
+    assert_eq!(*log.borrow(), ["r1 0", "r2 1"]);
+
+    // at most 2 tasks hold the permits
+    let exec = Executor::new();
+    let sem = Rc::new(Semaphore::new(2));
+    let (cur, max) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+    for _ in 0..5 {
+        let (sem, cur, max) = (sem.clone(), cur.clone(), max.clone());
+        exec.spawn(move || {
+            let _permit = sem.acquire();
+            cur.set(cur.get() + 1);
+            max.set(max.get().max(cur.get()));
+            sched::yield_now();
+            cur.set(cur.get() - 1);
+        });
+    }
+    exec.run_until_idle();
+    assert_eq!(max.get(), 2);
+    assert_eq!(sem.available_permits(), 2);
+
+    // condvar and notify
+    let exec = Executor::new();
+    let pair = Rc::new((Mutex::new(0), Condvar::new()));
+    let notify = Rc::new(Notify::new());
+    let p = pair.clone();
+    let n = notify.clone();
+    let h = exec.spawn(move || {
+        n.wait();
+        let (lock, cv) = &*p;
+        *cv.wait_while(lock.lock(), |v| *v < 3)
+    });
+    let p = pair.clone();
+    exec.spawn(move || {
+        for _ in 0..3 {
+            let (lock, cv) = &*p;
+            *lock.lock() += 1;
+            cv.notify_all();
+            sched::yield_now();
+        }
+    });
+    // kept for the next wait
+    notify.notify_one();
+    assert_eq!(h.join().unwrap(), 3);
+    exec.run_until_idle();
+
+    // across the workers, the lock is held while the task yields
+    let rt = Runtime::new(4).unwrap();
+    let lock = Arc::new(Mutex::new(0));
+    let barrier = Arc::new(Barrier::new(8));
+    let leaders = Arc::new(AtomicUsize::new(0));
+    let hs: Vec<_> = (0..8)
+        .map(|_| {
+            let (lock, barrier, leaders) = (lock.clone(), barrier.clone(), leaders.clone());
+            rt.spawn(move || {
+                for _ in 0..100 {
+                    let mut v = lock.lock();
+                    let old = *v;
+                    sched::yield_now();
+                    *v = old + 1;
+                }
+                if barrier.wait().is_leader() {
+                    leaders.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+    for h in hs {
+        h.join().unwrap();
+    }
+    assert_eq!(*lock.lock(), 800);
+    assert_eq!(leaders.load(Ordering::Relaxed), 1);
+
+    // the cancelled waiter leaves the queue
+    let sem = Arc::new(Semaphore::new(0));
+    let s = sem.clone();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let h = rt.spawn(move || {
+        tx.send(()).unwrap();
+        drop(s.acquire());
+    });
+    rx.recv().unwrap();
+    drop(rt);
+    assert!(h.join().is_err());
+    sem.add_permits(1);
+    assert!(sem.try_acquire().is_some());
+
+    // out of the tasks it blocks the threads
+    let lock = Arc::new(Mutex::new(0));
+    let notify = Arc::new(Notify::new());
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    *lock.lock() += 1;
+                }
+            });
+        }
+        s.spawn(|| notify.wait());
+        notify.notify_one();
+    });
+    assert_eq!(*lock.lock(), 4000);
+
+    // a generator nested in a task can't block the executor
+    let exec = Executor::new();
+    let sem = Rc::new(Semaphore::new(0));
+    let h = exec.spawn(move || {
+        let mut g = Gn::<()>::new_scoped_local(move |_| {
+            drop(sem.acquire());
+        });
+        g.resume();
+    });
+    let err = h.join().unwrap_err();
+    let p = err.downcast_ref::<GeneratorPanic>().unwrap();
+    assert_eq!(
+        p.message(),
+        Some("park in a generator nested in a task would block the executor")
+    );
+}
@@ -0,0 +1,95 @@
+extern crate generator;
+
+use generator::*;
+
+#[test]
+fn test_generator_observer() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(GeneratorId, &'static str)>>);
+    impl Recorder {
+        fn events(&self, id: GeneratorId) -> Vec<&'static str> {
+            let events = self.0.lock().unwrap();
+            events.iter().filter(|e| e.0 == id).map(|e| e.1).collect()
+        }
+    }
+    impl GeneratorObserver for Recorder {
+        fn on_create(&self, id: GeneratorId) {
+            self.0.lock().unwrap().push((id, "create"));
+        }
+        fn on_resume(&self, id: GeneratorId) {
+            self.0.lock().unwrap().push((id, "resume"));
+        }
+        fn on_yield(&self, id: GeneratorId, _: Duration) {
+            self.0.lock().unwrap().push((id, "yield"));
+        }
+        fn on_complete(&self, id: GeneratorId, _: Duration) {
+            self.0.lock().unwrap().push((id, "complete"));
+        }
+        fn on_cancel(&self, id: GeneratorId) {
+            self.0.lock().unwrap().push((id, "cancel"));
+        }
+        fn on_panic(&self, id: GeneratorId, _: Duration) {
+            self.0.lock().unwrap().push((id, "panic"));
+        }
+        fn on_drop(&self, id: GeneratorId, _: Duration) {
+            self.0.lock().unwrap().push((id, "drop"));
+        }
+    }
+
+    let rec = Arc::new(Recorder::default());
+    set_default_observer(Some(rec.clone()));
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        s.yield_(1);
+        2
+    });
+    set_default_observer(None);
+    assert!(default_observer().is_none());
+    let id = g.id();
+    assert_eq!(g.by_ref().sum::<i32>(), 3);
+    drop(g);
+    assert_eq!(
+        rec.events(id),
+        ["create", "resume", "yield", "resume", "complete", "drop"]
+    );
+
+    // cancelled when dropped
+    let mut g = Gn::<()>::new_scoped(|mut s| loop {
+        s.yield_(0);
+    });
+    g.set_observer(Some(rec.clone()));
+    let id = g.id();
+    g.resume();
+    // the backtrace capture is not observed
+    let _ = g.backtrace();
+    drop(g);
+    assert_eq!(rec.events(id), ["resume", "yield", "cancel", "drop"]);
+
+    let mut g = Gn::<()>::new_scoped(|_| -> i32 { panic!("observed") });
+    g.set_observer(Some(rec.clone()));
+    g.set_panic_report(PanicReport::Silent);
+    let id = g.id();
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| g.resume())).is_err());
+    g.set_observer(None);
+    drop(g);
+    assert_eq!(rec.events(id), ["resume", "panic"]);
+
+    // the delegator sees the resumes of its delegate
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        s.yield_from(Gn::new_scoped(|mut s| {
+            s.yield_(1);
+            2
+        }));
+        3
+    });
+    g.set_observer(Some(rec.clone()));
+    let id = g.id();
+    assert_eq!(g.by_ref().collect::<Vec<_>>(), [1, 2, 3]);
+    drop(g);
+    assert_eq!(
+        rec.events(id),
+        ["resume", "yield", "resume", "yield", "resume", "complete", "drop"]
+    );
+}
@@ -0,0 +1,136 @@
+extern crate generator;
+
+use generator::*;
+
+use std::sync::Mutex;
+
+// the panic hook is process wide, the tests change it one by one
+static HOOK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_generator_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let _lock = HOOK.lock().unwrap();
+    let _hook = install_panic_hook();
+
+    let mut inner =
+        Gn::<()>::new_scoped(|_| -> i32 { panic!("inner {}", std::hint::black_box(42)) });
+    let line = line!() - 1;
+    inner.set_name("inner");
+    let inner_id = inner.id();
+
+    let mut outer = Gn::<()>::new_scoped(move |_| inner.resume());
+    outer.set_name("outer");
+
+    let err = catch_unwind(AssertUnwindSafe(|| outer.resume())).unwrap_err();
+    let p = err.downcast::<GeneratorPanic>().unwrap();
+    assert_eq!(p.id(), inner_id);
+    assert_eq!(p.name(), Some("inner"));
+    assert_eq!(p.message(), Some("inner 42"));
+    let location = p.location().unwrap();
+    assert_eq!((location.file.as_str(), location.line), (file!(), line));
+    let chain: Vec<_> = p.chain().iter().map(|(_, name)| name.as_deref()).collect();
+    assert_eq!(chain, [Some("inner"), Some("outer")]);
+    assert_eq!(
+        p.to_string(),
+        format!(
+            "generator 'inner' (id {inner_id}) panicked at {location}: inner 42\n  \
+             in generator 'outer' (id {})",
+            outer.id()
+        )
+    );
+    assert_eq!(
+        p.into_payload().downcast_ref::<String>().unwrap(),
+        "inner 42"
+    );
+    assert_eq!(outer.state(), GeneratorState::Panicked);
+
+    // the site of a panic caught by the generator itself is not reused
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        let _ = catch_unwind(|| panic!("caught"));
+        s.yield_(0);
+        std::panic::resume_unwind(Box::new("rethrown"))
+    });
+    g.set_panic_report(PanicReport::Silent);
+    assert_eq!(g.resume(), Some(0));
+    let err = catch_unwind(AssertUnwindSafe(|| g.resume())).unwrap_err();
+    let p = err.downcast::<GeneratorPanic>().unwrap();
+    assert_eq!(p.message(), Some("rethrown"));
+    assert!(p.location().is_none());
+}
+
+#[test]
+fn test_panic_report() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    let _lock = HOOK.lock().unwrap();
+    let _hook = install_panic_hook();
+
+    assert!(matches!(default_panic_report(), PanicReport::Site));
+
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let r = reported.clone();
+    let mut inner = Gn::<()>::new_scoped(|_| -> i32 { panic!("reported") });
+    inner.set_panic_report(PanicReport::Callback(Arc::new(move |p| {
+        r.lock().unwrap().push(p.message().unwrap().to_owned());
+    })));
+    assert!(matches!(inner.panic_report(), PanicReport::Callback(_)));
+
+    // the callback is only called where the panic happens
+    let mut outer = Gn::<()>::new_scoped(move |_| inner.resume());
+    outer.set_panic_report(PanicReport::Silent);
+    let err = catch_unwind(AssertUnwindSafe(|| outer.resume())).unwrap_err();
+    assert_eq!(*reported.lock().unwrap(), ["reported"]);
+    assert_eq!(
+        err.downcast_ref::<GeneratorPanic>().unwrap().chain().len(),
+        2
+    );
+
+    let mut g = Gn::<()>::new_scoped(|_| -> i32 { panic!("silent") });
+    g.set_panic_report(PanicReport::Silent);
+    let err = catch_unwind(AssertUnwindSafe(|| g.resume())).unwrap_err();
+    let p = err.downcast_ref::<GeneratorPanic>().unwrap();
+    assert_eq!(p.message(), Some("silent"));
+    assert!(p.location().is_some());
+}
+
+#[test]
+fn test_panic_hook_guard() {
+    use std::panic::{self, catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let _lock = HOOK.lock().unwrap();
+    let prev = panic::take_hook();
+    panic::set_hook(Box::new(|_| {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    }));
+    let silent_panic = || {
+        let mut g = Gn::<()>::new_scoped(|_| -> i32 { panic!("silent") });
+        g.set_panic_report(PanicReport::Silent);
+        assert!(catch_unwind(AssertUnwindSafe(|| g.resume())).is_err());
+    };
+
+    // the hook before the guards is restored by the last one
+    let hook = install_panic_hook();
+    let guard = install_panic_hook();
+    silent_panic();
+    drop(guard);
+    silent_panic();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+    drop(hook);
+    silent_panic();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    // the hook set afterwards is not replaced
+    let hook = install_panic_hook();
+    panic::set_hook(Box::new(|_| {
+        CALLS.fetch_add(10, Ordering::SeqCst);
+    }));
+    drop(hook);
+    silent_panic();
+    assert_eq!(CALLS.load(Ordering::SeqCst), 11);
+    panic::set_hook(prev);
+}
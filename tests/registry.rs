@@ -0,0 +1,105 @@
+extern crate generator;
+
+use generator::*;
+
+use std::sync::Mutex;
+
+// the registry state is process wide, the tests change it one by one
+static REGISTRY: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_registry() {
+    let _lock = REGISTRY.lock().unwrap();
+    registry::enable();
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        let buf = [0u8; 256];
+        s.yield_(std::hint::black_box(&buf).len());
+        0
+    });
+    let line = line!() - 5;
+    g.set_name("registered");
+    g.resume();
+
+    let info = registry::snapshot()
+        .into_iter()
+        .find(|info| info.id == g.id())
+        .unwrap();
+    assert_eq!(info.name.as_deref(), Some("registered"));
+    assert_eq!(info.state, GeneratorState::Suspended);
+    assert_eq!(info.location.file(), file!());
+    assert_eq!(info.location.line(), line);
+    assert_eq!(info.thread, "test_registry");
+    assert!(info.stack_used > 256 / std::mem::size_of::<usize>());
+    assert!(info.stack_used < info.stack_size);
+    assert_eq!(info.yielded_at.unwrap().line(), line + 2);
+
+    let mut out = Vec::new();
+    registry::dump(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains(&format!("generator {} 'registered' Suspended", g.id())));
+
+    let id = g.id();
+    drop(g);
+    assert!(registry::snapshot().iter().all(|info| info.id != id));
+    registry::disable();
+}
+
+#[test]
+fn test_watchdog() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    let _lock = REGISTRY.lock().unwrap();
+    // the watchdog enables the registry, the generator created afterwards is watched
+    let (tx, rx) = mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    let watchdog = registry::Watchdog::start(Duration::from_millis(50), move |info, elapsed| {
+        let _ = tx.lock().unwrap().send((
+            info.id,
+            info.name.clone(),
+            info.thread.clone(),
+            info.yielded_at,
+            elapsed,
+        ));
+    })
+    .unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop2 = stop.clone();
+    let mut g = Gn::<()>::new_scoped(move |mut s| {
+        s.yield_(1);
+        // stuck without yielding
+        while !stop2.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        0
+    });
+    let line = line!() - 7;
+    g.set_name("stuck");
+    assert_eq!(g.resume(), Some(1));
+    let id = g.id();
+
+    let t = std::thread::Builder::new()
+        .name("stuck-thread".into())
+        .spawn(move || g.resume())
+        .unwrap();
+
+    let (name, thread, yielded_at, elapsed) = loop {
+        let (stuck, name, thread, yielded_at, elapsed) =
+            rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        if stuck == id {
+            break (name, thread, yielded_at, elapsed);
+        }
+    };
+    assert_eq!(name.as_deref(), Some("stuck"));
+    assert_eq!(thread, "stuck-thread");
+    assert_eq!(yielded_at.unwrap().line(), line);
+    assert!(elapsed >= Duration::from_millis(50));
+
+    stop.store(true, Ordering::Relaxed);
+    assert_eq!(t.join().unwrap(), Some(0));
+    drop(watchdog);
+    // restored to the state before the watchdog
+    assert!(!registry::is_enabled());
+}
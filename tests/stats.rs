@@ -0,0 +1,44 @@
+extern crate generator;
+
+use generator::*;
+
+#[test]
+fn test_generator_stats() {
+    let mut g = Gn::<()>::new_scoped(|mut s| {
+        for i in 0..3 {
+            // burn some cpu
+            let mut v = 0u64;
+            for j in 0..100_000 {
+                v = std::hint::black_box(v.wrapping_add(j));
+            }
+            s.yield_(v + i);
+        }
+        0
+    });
+    assert!(g.stats().is_none());
+    g.enable_stats();
+    assert_eq!(g.stats(), Some(Default::default()));
+
+    registry::enable();
+    let mut h = Gn::<()>::new_scoped(|mut s| {
+        s.yield_(1);
+        2
+    });
+    h.enable_stats();
+    h.resume();
+
+    assert_eq!(g.by_ref().count(), 4);
+    let stats = g.stats().unwrap();
+    assert_eq!((stats.resumes, stats.yields), (4, 3));
+    if cfg!(unix) {
+        assert!(stats.cpu_time > std::time::Duration::ZERO);
+    }
+
+    let info = registry::snapshot()
+        .into_iter()
+        .find(|info| info.id == h.id())
+        .unwrap();
+    let stats = info.stats.unwrap();
+    assert_eq!((stats.resumes, stats.yields), (1, 1));
+    assert!(info.to_string().contains(", 1 resumes, 1 yields, cpu "));
+}